@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::signature::Signature;
+use crate::types::ValueType;
+
+/// a function every Virtuoso session provides
+#[derive(Debug, Clone)]
+pub struct Builtin {
+    pub signature: Signature,
+    pub description: String,
+}
+
+fn parse_builtins(data: &str) -> HashMap<String, Builtin> {
+    data.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .filter_map(|line| {
+            let (signature, description) = line.split_once(" ; ").unwrap_or((line, ""));
+            let (signature, returns) = signature.split_once(" => ").unwrap_or((signature, ""));
+            let signature = Signature {
+                returns: ValueType::of_parameter(returns),
+                ..Signature::parse(signature)?
+            };
+            Some((
+                signature.name.clone(),
+                Builtin {
+                    signature,
+                    description: description.to_string(),
+                },
+            ))
+        })
+        .collect()
+}
+
+impl Builtin {
+    pub fn markdown(&self) -> String {
+        let returns = self
+            .signature
+            .returns
+            .map_or(String::new(), |returns| format!(" => {}", returns.name()));
+        format!(
+            "```lisp\n{}{returns}\n```\n\n*built-in*\n\n---\n{}",
+            self.signature.label(),
+            self.description
+        )
+    }
+}
+
+/// the built-in function called `name`
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    static BUILTINS: OnceLock<HashMap<String, Builtin>> = OnceLock::new();
+    BUILTINS
+        .get_or_init(|| parse_builtins(include_str!("builtins.txt")))
+        .get(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::types::ValueType;
+
+    use super::builtin;
+
+    #[test]
+    fn builtin_signatures() {
+        let printf = builtin("printf").unwrap();
+        assert_eq!(printf.signature.required, vec!["t_format"]);
+        assert!(printf.signature.rest.is_some());
+        assert_eq!(printf.description, "prints formatted output");
+        assert_eq!(printf.signature.returns, None);
+        assert_eq!(
+            builtin("strlen").unwrap().markdown(),
+            "```lisp\nstrlen(t_string) => fixnum\n```\n\n*built-in*\n\n---\nnumber of characters of a string"
+        );
+        assert_eq!(
+            builtin("strcat").unwrap().signature.returns,
+            Some(ValueType::String)
+        );
+
+        let field = builtin("hiCreateStringField").unwrap();
+        assert!(field.signature.keys.contains(&"callback".to_string()));
+        assert!(builtin("getWorkingDir")
+            .unwrap()
+            .signature
+            .required
+            .is_empty());
+        assert!(builtin("myOwnFunction").is_none());
+    }
+}
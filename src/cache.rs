@@ -1,44 +1,356 @@
-use std::fs::read_to_string;
+use std::fmt;
+use std::fs::{metadata, read};
+use std::io;
+use std::sync::mpsc::sync_channel;
+use std::sync::Mutex;
+use std::thread;
+use std::time::SystemTime;
 
 use dashmap::DashMap;
 use log::info;
-use tower_lsp::lsp_types::Diagnostic;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Diagnostic, Range};
 
-use crate::{skill::parse_skill_content, token::Token};
+use crate::{
+    encoding::{encoding, LineIndex},
+    index::SymbolIndex,
+    persist::DiskCache,
+    rules::{FILE_ENCODING, UNREADABLE_FILE},
+    skill::parse_skill_content,
+    token::Token,
+};
 
 #[derive(Debug)]
 pub struct TokenCache {
     pub symbols: DashMap<String, Vec<Token>>,
+    /// syntax errors found while parsing a file
+    pub errors: DashMap<String, Vec<Diagnostic>>,
+    /// what a file looked like when it was parsed
+    pub stamps: DashMap<String, FileStamp>,
+    /// global declarations of all cached files
+    pub index: SymbolIndex,
 }
 
-#[derive(Debug, Clone)]
-struct FileNotInCache;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileStamp {
+    pub modified: Option<SystemTime>,
+    pub hash: u64,
+}
+
+/// FNV-1a, unlike `DefaultHasher` it stays the same across releases
+pub fn content_hash(content: &str) -> u64 {
+    content.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// a file that could not be read, its diagnostics tell the user why
+#[derive(Debug)]
+pub struct UnreadableFile {
+    pub path: String,
+    pub error: io::Error,
+}
+
+impl fmt::Display for UnreadableFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot read '{}': {}", self.path, self.error)
+    }
+}
+
+/// reads `path`, text that is not UTF-8 is decoded as Latin-1 like older
+/// Cadence releases wrote it, with a diagnostic where decoding failed
+pub fn read_skill_file(path: &str) -> io::Result<(String, Option<Diagnostic>)> {
+    let bytes = read(path)?;
+    match String::from_utf8(bytes) {
+        Ok(content) => Ok((content, None)),
+        Err(err) => {
+            let valid = &err.as_bytes()[..err.utf8_error().valid_up_to()];
+            let valid = std::str::from_utf8(valid).unwrap_or_default();
+            let at = LineIndex::new(valid, encoding()).position(valid.len());
+            let content = err.as_bytes().iter().map(|&byte| byte as char).collect();
+            let warning = FILE_ENCODING.diagnostic(
+                Range::new(at, at),
+                "file is not valid UTF-8, it was read as Latin-1".to_string(),
+            );
+            Ok((content, Some(warning)))
+        }
+    }
+}
 
 impl TokenCache {
     pub fn new() -> TokenCache {
         TokenCache {
             symbols: DashMap::new(),
+            errors: DashMap::new(),
+            stamps: DashMap::new(),
+            index: SymbolIndex::default(),
         }
     }
 
-    pub fn update(&self, path: &str) -> (Vec<Token>, Vec<Diagnostic>) {
-        let content = read_to_string(path);
+    /// stores the parse result of `path`, keeping the index in sync
+    pub fn insert(&self, path: &str, tokens: Vec<Token>, errors: Vec<Diagnostic>) {
+        self.index.update(path, &tokens);
+        self.symbols.insert(path.to_owned(), tokens);
+        self.errors.insert(path.to_owned(), errors);
+    }
+
+    /// parses `path` again, a file that vanished is dropped and any other
+    /// failure is kept as diagnostic on the tokens read before
+    pub fn update(&self, path: &str) -> Result<(Vec<Token>, Vec<Diagnostic>), UnreadableFile> {
+        let modified = metadata(path).and_then(|meta| meta.modified()).ok();
 
-        match content {
-            Ok(skill_code) => {
-                let (parsed_tokens, parsed_errors) = parse_skill_content(&skill_code);
+        match read_skill_file(path) {
+            Ok((skill_code, decoding)) => {
+                let stamp = FileStamp {
+                    modified,
+                    hash: content_hash(&skill_code),
+                };
+                self.stamps.insert(path.to_owned(), stamp);
+                let (parsed_tokens, mut parsed_errors) = parse_skill_content(&skill_code);
+                parsed_errors.extend(decoding);
                 info!("parsed: {:?}", parsed_tokens.clone());
                 info!("parsed_errs: {:?}", parsed_errors.clone());
-                self.symbols.insert(path.to_owned(), parsed_tokens.clone());
-                (parsed_tokens, parsed_errors)
+                self.insert(path, parsed_tokens.clone(), parsed_errors.clone());
+                Ok((parsed_tokens, parsed_errors))
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.remove(path);
+                Err(UnreadableFile {
+                    path: path.to_owned(),
+                    error,
+                })
+            }
+            Err(error) => {
+                let tokens = self
+                    .symbols
+                    .get(path)
+                    .map(|toks| toks.clone())
+                    .unwrap_or_default();
+                let diagnostic = UNREADABLE_FILE
+                    .diagnostic(Range::default(), format!("cannot read file: {error}"));
+                // without a stamp it is neither stored on disk nor restored from there
+                self.stamps.remove(path);
+                self.insert(path, tokens, vec![diagnostic]);
+                Err(UnreadableFile {
+                    path: path.to_owned(),
+                    error,
+                })
             }
-            Err(_) => (vec![], vec![]), // FIXME: missing err diag
         }
     }
+
+    /// parses `files` on `workers` threads fed through a bounded queue, files unchanged
+    /// since they got `stored` are not parsed again, `parsed` is called after each file,
+    /// returns false if `cancelled` stopped it early
+    pub fn update_all(
+        &self,
+        files: &[String],
+        stored: &DiskCache,
+        workers: usize,
+        cancelled: impl Fn() -> bool + Sync,
+        parsed: impl Fn(&str) + Sync,
+    ) -> bool {
+        let (queue, jobs) = sync_channel::<&str>(workers * 4);
+        let jobs = Mutex::new(jobs);
+
+        thread::scope(|scope| {
+            for _ in 0..workers.max(1) {
+                scope.spawn(|| loop {
+                    let job = jobs.lock().unwrap().recv();
+                    match job {
+                        Ok(path) => {
+                            if !stored.restore(self, path) {
+                                if let Err(err) = self.update(path) {
+                                    info!("{}", err);
+                                }
+                            }
+                            parsed(path);
+                        }
+                        Err(_) => break,
+                    }
+                });
+            }
+
+            for path in files {
+                if cancelled() || queue.send(path).is_err() {
+                    break;
+                }
+            }
+            drop(queue);
+        });
+
+        !cancelled()
+    }
+
+    /// looks up the global declaration of `name` in any cached file
+    pub fn find_global(&self, name: &str) -> Option<Token> {
+        self.index
+            .lookup(name)
+            .into_iter()
+            .next()
+            .map(|decl| decl.token)
+    }
+
+    pub fn remove(&self, path: &str) {
+        self.symbols.remove(path);
+        self.errors.remove(path);
+        self.stamps.remove(path);
+        self.index.remove(path);
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    use tower_lsp::lsp_types::Position;
+
+    use crate::persist::DiskCache;
+    use crate::token::TokenKind;
+
+    use super::TokenCache;
+
+    /// writes `count` files of `lines` assignments each, mimicking a generated PDK tree
+    fn corpus(name: &str, count: usize, lines: usize) -> Vec<String> {
+        let root = std::env::temp_dir().join(format!("srls-{}-{}", name, std::process::id()));
+        create_dir_all(&root).unwrap();
+        (0..count)
+            .map(|file| {
+                let path = root.join(format!("cell{file}.il"));
+                let content: String = (0..lines)
+                    .map(|line| format!("cell{file}_var{line} = list(\"value\" {line} t)\n"))
+                    .collect();
+                write(&path, content).unwrap();
+                path.to_str().unwrap().to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unreadable_files() {
+        let files = corpus("unreadable", 2, 1);
+        let cache = TokenCache::new();
+        write(&files[0], b"name = \"M\xfcller\"\nother = 1\n").unwrap();
+        let (tokens, errors) = cache.update(&files[0]).unwrap();
+        assert_eq!(tokens[0].info.as_deref(), Some("name = \"M\u{fc}ller\""));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].range.start, Position::new(0, 9));
+
+        cache.update(&files[1]).unwrap();
+        std::fs::remove_file(&files[1]).unwrap();
+        assert!(cache.update(&files[1]).is_err());
+        assert!(!cache.symbols.contains_key(&files[1]));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&files[0], std::fs::Permissions::from_mode(0o000)).unwrap();
+            // permissions do not stop root
+            if std::fs::read(&files[0]).is_err() {
+                let err = cache.update(&files[0]).unwrap_err();
+                assert_eq!(err.error.kind(), std::io::ErrorKind::PermissionDenied);
+                assert_eq!(cache.symbols.get(&files[0]).unwrap().len(), tokens.len());
+                assert!(cache.errors.get(&files[0]).unwrap()[0]
+                    .message
+                    .starts_with("cannot read file"));
+                assert!(!cache.stamps.contains_key(&files[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn update_all() {
+        let files = corpus("update-all", 40, 5);
+        let cache = TokenCache::new();
+        let parsed = AtomicUsize::new(0);
+
+        assert!(cache.update_all(
+            &files,
+            &DiskCache::default(),
+            4,
+            || false,
+            |_| {
+                parsed.fetch_add(1, Ordering::Relaxed);
+            }
+        ));
+        assert_eq!(parsed.load(Ordering::Relaxed), files.len());
+        assert_eq!(cache.symbols.len(), files.len());
+        assert!(files.iter().all(|f| {
+            let tokens = cache.symbols.get(f).unwrap();
+            tokens
+                .iter()
+                .filter(|tok| tok.kind == TokenKind::VariableAssignment)
+                .count()
+                == 5
+        }));
+    }
+
+    #[test]
+    fn update_all_cancelled() {
+        let files = corpus("update-all-cancelled", 20, 1);
+        let cache = TokenCache::new();
+
+        assert!(!cache.update_all(&files, &DiskCache::default(), 2, || true, |_| {}));
+        assert!(cache.symbols.is_empty());
+    }
+
+    /// indexes generated corpora and checks the time grows linearly with the number of
+    /// files and shrinks with more workers, timing depends on the machine so run it with
+    /// `cargo test --release -- --ignored --nocapture index_throughput`
     #[test]
-    fn insert() {}
+    #[ignore]
+    fn index_throughput() {
+        // the fastest of a few runs, other tests compete for the same cores
+        let index = |files: &[String], workers: usize| {
+            let elapsed = (0..3)
+                .map(|_| {
+                    let cache = TokenCache::new();
+                    let start = Instant::now();
+                    assert!(cache.update_all(
+                        files,
+                        &DiskCache::default(),
+                        workers,
+                        || false,
+                        |_| {}
+                    ));
+                    assert_eq!(cache.symbols.len(), files.len());
+                    start.elapsed()
+                })
+                .min()
+                .unwrap();
+            println!(
+                "{} files on {} workers: {:?}",
+                files.len(),
+                workers,
+                elapsed
+            );
+            elapsed
+        };
+        let small = corpus("throughput-small", 150, 20);
+        let large = corpus("throughput-large", 300, 20);
+
+        let small_serial = index(&small, 1);
+        let large_serial = index(&large, 1);
+        // twice the files, allowing for noise but not for quadratic growth
+        assert!(
+            large_serial < small_serial * 3,
+            "{large_serial:?} for twice the files of {small_serial:?}"
+        );
+
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        if workers > 1 {
+            let large_parallel = index(&large, workers);
+            assert!(
+                large_parallel < large_serial,
+                "{large_parallel:?} on {workers} workers, {large_serial:?} on one"
+            );
+        }
+
+        for files in [small, large] {
+            remove_dir_all(Path::new(&files[0]).parent().unwrap()).unwrap();
+        }
+    }
 }
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::deprecated::{deprecation, Deprecation};
+use crate::rules::RuleLevel;
+
+/// user settings, sent as `initializationOptions` or through
+/// `workspace/didChangeConfiguration` either directly or below an `srls` key
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Config {
+    /// read-only directories indexed for definitions, never reported on
+    pub library_roots: Vec<PathBuf>,
+
+    /// directories relative `load` calls are resolved against, like `setSkillPath`
+    pub skill_path: Vec<PathBuf>,
+
+    /// globs like in `.gitignore`, only matching files are indexed if there are any
+    pub include: Vec<String>,
+
+    /// globs like in `.gitignore` of files and directories that are never indexed
+    pub exclude: Vec<String>,
+
+    /// files larger than this many bytes are skipped, defaults to `DEFAULT_MAX_FILE_SIZE`
+    pub max_file_size: Option<u64>,
+
+    /// prefixes every global variable and procedure has to start with, like the
+    /// uppercase prefix registered with Cadence, nothing is checked without any
+    pub prefixes: Vec<String>,
+
+    /// in-house deprecations by function name, next to the shipped ones
+    pub deprecated: HashMap<String, Deprecation>,
+
+    /// rule codes mapped to `off` or the severity they are reported with
+    pub rules: HashMap<String, RuleLevel>,
+}
+
+/// generated SKILL can get huge without containing anything worth completing
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 8 * 1024 * 1024;
+
+/// replaces `$VAR` and `${VAR}` with the value from the environment
+fn expand_env(path: &Path) -> PathBuf {
+    let variable = Regex::new(r"\$\{?(\w+)\}?").unwrap();
+    let expanded = variable.replace_all(path.to_str().unwrap_or(""), |caps: &Captures| {
+        std::env::var(&caps[1]).unwrap_or_default()
+    });
+    PathBuf::from(expanded.as_ref())
+}
+
+impl Config {
+    pub fn from_value(value: Option<Value>) -> Config {
+        let value = match value {
+            Some(Value::Object(mut settings)) => {
+                settings.remove("srls").unwrap_or(Value::Object(settings))
+            }
+            _ => return Config::default(),
+        };
+
+        serde_json::from_value(value).unwrap_or_default()
+    }
+
+    /// expands environment variables and anchors relative entries at `root`
+    pub fn resolved(mut self, root: &Path) -> Config {
+        let resolve = |path: &PathBuf| root.join(expand_env(path));
+        self.library_roots = self.library_roots.iter().map(resolve).collect();
+        self.skill_path = self.skill_path.iter().map(resolve).collect();
+        self
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size.unwrap_or(DEFAULT_MAX_FILE_SIZE)
+    }
+
+    /// the deprecation of the function called `name`, settings win over the shipped list
+    pub fn deprecation(&self, name: &str) -> Option<&Deprecation> {
+        self.deprecated.get(name).or_else(|| deprecation(name))
+    }
+
+    pub fn is_library(&self, path: &str) -> bool {
+        self.library_roots
+            .iter()
+            .any(|root| Path::new(path).starts_with(root))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use serde_json::json;
+
+    use crate::rules::RuleLevel;
+
+    use super::Config;
+
+    #[test]
+    fn read_settings() {
+        std::env::set_var("SRLS_TEST_CDSHOME", "/opt/cadence");
+        let config = Config::from_value(Some(json!({
+            "srls": {
+                "libraryRoots": ["$SRLS_TEST_CDSHOME/local", "../team-utils"],
+                "skillPath": ["skill"],
+                "exclude": ["build/"],
+                "maxFileSize": 1024,
+                "rules": {"unused-variable": "hint"},
+                "prefixes": ["ACME"],
+                "deprecated": {"ACMEoldPlot": {"replacement": "ACMEplot"}},
+            }
+        })))
+        .resolved(Path::new("/work/pdk"));
+
+        assert_eq!(
+            config.library_roots,
+            vec![
+                PathBuf::from("/opt/cadence/local"),
+                PathBuf::from("/work/pdk/../team-utils")
+            ]
+        );
+        assert_eq!(config.skill_path, vec![PathBuf::from("/work/pdk/skill")]);
+        assert_eq!(config.exclude, vec!["build/"]);
+        assert_eq!(config.max_file_size(), 1024);
+        assert_eq!(config.rules["unused-variable"], RuleLevel::Hint);
+        assert_eq!(config.prefixes, vec!["ACME"]);
+        assert_eq!(
+            config
+                .deprecation("ACMEoldPlot")
+                .and_then(|deprecation| deprecation.replacement.as_deref()),
+            Some("ACMEplot")
+        );
+        assert!(config.deprecation("dbOpenCellView").is_some());
+        assert!(config.is_library("/opt/cadence/local/util.il"));
+        assert!(!config.is_library("/work/pdk/skill/util.il"));
+    }
+
+    #[test]
+    fn ignore_invalid_settings() {
+        assert_eq!(Config::from_value(None), Config::default());
+        assert_eq!(
+            Config::from_value(Some(json!({"skillPath": 3}))),
+            Config::default()
+        );
+    }
+}
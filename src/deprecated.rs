@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+
+/// what to use instead of a function that should not be called anymore
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Deprecation {
+    pub replacement: Option<String>,
+    /// the replacement takes the same arguments, calls can be renamed to it
+    pub rename: bool,
+    /// release the function was removed in, still usable before
+    pub removed_in: Option<String>,
+    pub note: Option<String>,
+}
+
+impl Deprecation {
+    pub fn message(&self, name: &str) -> String {
+        let mut message = match &self.removed_in {
+            Some(release) => format!("`{name}` was removed in {release}"),
+            None => format!("`{name}` is deprecated"),
+        };
+        if let Some(replacement) = &self.replacement {
+            message += &format!(", use `{replacement}` instead");
+        }
+        if let Some(note) = &self.note {
+            message += &format!("\n{note}");
+        }
+        message
+    }
+}
+
+fn optional(part: Option<&str>) -> Option<String> {
+    part.map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+}
+
+fn parse_deprecations(data: &str) -> HashMap<String, Deprecation> {
+    data.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(';'))
+        .map(|line| {
+            let mut parts = line.splitn(3, ';');
+            let head = parts.next().unwrap_or_default();
+            let (name, replacement, rename) = match head.split_once("=>") {
+                Some((name, replacement)) => (name, Some(replacement), true),
+                None => match head.split_once("->") {
+                    Some((name, replacement)) => (name, Some(replacement), false),
+                    None => (head, None, false),
+                },
+            };
+            (
+                name.trim().to_string(),
+                Deprecation {
+                    replacement: optional(replacement),
+                    rename,
+                    removed_in: optional(parts.next()),
+                    note: optional(parts.next()),
+                },
+            )
+        })
+        .collect()
+}
+
+fn shipped() -> &'static HashMap<String, Deprecation> {
+    static DEPRECATED: OnceLock<HashMap<String, Deprecation>> = OnceLock::new();
+    DEPRECATED.get_or_init(|| parse_deprecations(include_str!("deprecated.txt")))
+}
+
+/// the shipped deprecation of the built-in function called `name`
+pub fn deprecation(name: &str) -> Option<&'static Deprecation> {
+    shipped().get(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deprecation, parse_deprecations, shipped, Deprecation};
+
+    #[test]
+    fn deprecated_functions() {
+        let parsed = parse_deprecations(
+            "; comment\noldFun -> newFun ; 6.1 ; slower\ngone\nrenamed => newName\n",
+        );
+        assert_eq!(
+            parsed["oldFun"],
+            Deprecation {
+                replacement: Some("newFun".to_string()),
+                rename: false,
+                removed_in: Some("6.1".to_string()),
+                note: Some("slower".to_string()),
+            }
+        );
+        assert_eq!(
+            parsed["renamed"],
+            Deprecation {
+                replacement: Some("newName".to_string()),
+                rename: true,
+                ..Default::default()
+            }
+        );
+        assert_eq!(parsed["gone"], Deprecation::default());
+        assert_eq!(
+            parsed["oldFun"].message("oldFun"),
+            "`oldFun` was removed in 6.1, use `newFun` instead\nslower"
+        );
+        assert_eq!(parsed["gone"].message("gone"), "`gone` is deprecated");
+
+        let shipped = deprecation("dbOpenCellView").unwrap();
+        assert_eq!(shipped.replacement.as_deref(), Some("dbOpenCellViewByType"));
+        assert!(!shipped.rename);
+        assert_eq!(shipped.removed_in, None);
+        assert!(shipped
+            .message("dbOpenCellView")
+            .starts_with("`dbOpenCellView` is deprecated, use `dbOpenCellViewByType` instead\n"));
+        assert!(deprecation("dbOpenCellViewByType").is_none());
+    }
+
+    #[test]
+    fn shipped_deprecations() {
+        let shipped = shipped();
+        assert!(shipped
+            .values()
+            .any(|deprecation| deprecation.rename && deprecation.replacement.is_some()));
+        // a replacement is never deprecated itself
+        for (name, shipped) in shipped {
+            let replacement = shipped.replacement.as_deref();
+            assert!(
+                replacement.is_none_or(|replacement| deprecation(replacement).is_none()),
+                "{name}"
+            );
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::signature::Signature;
+use crate::types::ValueType;
+
+/// a `@param name type description` or `@return type description` tag
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    /// the type as written, only set if it names a known type
+    pub type_name: Option<String>,
+    pub description: String,
+}
+
+impl Tag {
+    pub fn value_type(&self) -> Option<ValueType> {
+        ValueType::of_name(self.type_name.as_deref()?)
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut line = String::new();
+        if let Some(type_name) = &self.type_name {
+            line += &format!(" *{type_name}*");
+        }
+        if !self.description.is_empty() {
+            line += &format!(" {}", self.description);
+        }
+        line
+    }
+}
+
+/// the text of a `;;;` comment block, indentation past the first space is kept for Markdown
+pub fn comment_text(comment: &str) -> String {
+    comment
+        .lines()
+        .map(|line| {
+            let line = line.trim_start().trim_start_matches(";;;");
+            line.strip_prefix(' ').unwrap_or(line).trim_end()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// the text of a docstring literal at the start of a procedure body,
+/// lines after the first lose the indentation they share
+pub fn string_text(literal: &str) -> String {
+    let mut text = String::new();
+    let mut chars = literal.trim_matches('"').chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some(escaped) => text.push(escaped),
+                None => {}
+            },
+            (c, false) => text.push(c),
+        }
+    }
+    let indent = text
+        .lines()
+        .skip(1)
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    text.lines()
+        .enumerate()
+        .map(|(at, line)| match at {
+            0 => line.trim(),
+            _ => line.get(indent..).unwrap_or_default().trim_end(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// a `;;;` docstring split into its text and tags
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Docstring {
+    pub summary: String,
+    pub params: Vec<Tag>,
+    pub returns: Option<Tag>,
+    pub examples: Vec<String>,
+}
+
+fn first_word(text: &str) -> (&str, &str) {
+    let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    (word, rest.trim_start())
+}
+
+/// splits `words` into a tag, the type is optional and may be in braces
+fn tag(name: Option<&str>, words: &str) -> Tag {
+    let (name, rest) = match name {
+        Some(name) => (name, words.trim()),
+        None => first_word(words.trim()),
+    };
+    let (candidate, after) = first_word(rest);
+    let candidate = candidate.trim_matches(|c| c == '{' || c == '}');
+    let (type_name, description) = match ValueType::of_name(candidate) {
+        Some(_) => (Some(candidate.to_string()), after),
+        None => (None, rest),
+    };
+    Tag {
+        name: name.to_string(),
+        type_name,
+        description: description.to_string(),
+    }
+}
+
+impl Docstring {
+    pub fn parse(text: &str) -> Docstring {
+        enum Section {
+            Summary,
+            Param,
+            Return,
+            Example,
+        }
+        let mut doc = Docstring::default();
+        let mut section = Section::Summary;
+
+        for line in text.lines() {
+            let trimmed = line.trim();
+            let (keyword, words) = trimmed
+                .split_once(char::is_whitespace)
+                .unwrap_or((trimmed, ""));
+            match keyword {
+                "@param" => {
+                    doc.params.push(tag(None, words));
+                    section = Section::Param;
+                }
+                "@return" | "@returns" => {
+                    doc.returns = Some(tag(Some(""), words));
+                    section = Section::Return;
+                }
+                "@example" => {
+                    doc.examples.push(words.to_string());
+                    section = Section::Example;
+                }
+                _ => {
+                    let continued = match section {
+                        Section::Summary => {
+                            // Markdown, paragraphs and indented lines stay as written
+                            doc.summary.push('\n');
+                            doc.summary.push_str(line.trim_end());
+                            continue;
+                        }
+                        Section::Param => &mut doc.params.last_mut().unwrap().description,
+                        Section::Return => &mut doc.returns.as_mut().unwrap().description,
+                        Section::Example => {
+                            let example = doc.examples.last_mut().unwrap();
+                            if !example.is_empty() {
+                                example.push('\n');
+                            }
+                            example.push_str(line);
+                            continue;
+                        }
+                    };
+                    if !continued.is_empty() && !trimmed.is_empty() {
+                        continued.push(' ');
+                    }
+                    continued.push_str(trimmed);
+                }
+            }
+        }
+
+        doc.summary = doc.summary.trim().to_string();
+        for example in doc.examples.iter_mut() {
+            *example = example.trim_end().to_string();
+        }
+        doc
+    }
+
+    pub fn param(&self, name: &str) -> Option<&Tag> {
+        self.params.iter().find(|param| param.name == name)
+    }
+
+    /// the parameter and return types the tags declare
+    pub fn annotate(&self, signature: &mut Signature) {
+        for param in self.params.iter() {
+            if let Some(value_type) = param.value_type() {
+                signature.types.insert(param.name.clone(), value_type);
+            }
+        }
+        if let Some(returns) = self.returns.as_ref().and_then(Tag::value_type) {
+            signature.returns = Some(returns);
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let mut parts = vec![];
+        if !self.summary.is_empty() {
+            parts.push(self.summary.clone());
+        }
+        if !self.params.is_empty() {
+            let params: Vec<String> = self
+                .params
+                .iter()
+                .map(|param| format!("- `{}`{}", param.name, param.markdown()))
+                .collect();
+            parts.push(format!("**Parameters**\n{}", params.join("\n")));
+        }
+        if let Some(returns) = &self.returns {
+            parts.push(format!("**Returns**{}", returns.markdown()));
+        }
+        for example in self.examples.iter() {
+            parts.push(format!("**Example**\n```lisp\n{example}\n```"));
+        }
+        parts.join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::signature::Signature;
+    use crate::types::ValueType;
+
+    use super::{comment_text, string_text, Docstring, Tag};
+
+    #[test]
+    fn docstring_tags() {
+        let doc = Docstring::parse(
+            "Places a via between two layers.\n\
+             @param cv dbObject the cellview\n\
+             @param layers {list} layer names,\n  top one first\n\
+             @param name without a type\n\
+             @return t/nil whether it fit\n\
+             @example\n  ACMEplaceVia(cv '(\"M1\" \"M2\"))\n  ; => t\n",
+        );
+        assert_eq!(doc.summary, "Places a via between two layers.");
+        assert_eq!(
+            doc.params[1],
+            Tag {
+                name: "layers".to_string(),
+                type_name: Some("list".to_string()),
+                description: "layer names, top one first".to_string(),
+            }
+        );
+        assert_eq!(doc.param("name").unwrap().type_name, None);
+        assert_eq!(doc.param("name").unwrap().description, "without a type");
+        assert_eq!(
+            doc.returns.as_ref().unwrap().value_type(),
+            Some(ValueType::Boolean)
+        );
+        assert_eq!(
+            doc.examples,
+            vec!["  ACMEplaceVia(cv '(\"M1\" \"M2\"))\n  ; => t"]
+        );
+
+        let mut signature = Signature::parse("ACMEplaceVia(cv layers name)").unwrap();
+        doc.annotate(&mut signature);
+        assert_eq!(signature.parameter_type("cv"), Some(ValueType::DbObject));
+        assert_eq!(signature.parameter_type("name"), None);
+        assert_eq!(signature.returns, Some(ValueType::Boolean));
+
+        assert_eq!(
+            doc.markdown(),
+            "Places a via between two layers.\n\n\
+             **Parameters**\n\
+             - `cv` *dbObject* the cellview\n\
+             - `layers` *list* layer names, top one first\n\
+             - `name` without a type\n\n\
+             **Returns** *t/nil* whether it fit\n\n\
+             **Example**\n```lisp\n  ACMEplaceVia(cv '(\"M1\" \"M2\"))\n  ; => t\n```"
+        );
+        assert_eq!(Docstring::parse("just text").markdown(), "just text");
+        assert_eq!(
+            Docstring::parse("first\n\n- one\n  - nested\n@return string").summary,
+            "first\n\n- one\n  - nested"
+        );
+    }
+
+    #[test]
+    fn docstring_sources() {
+        assert_eq!(
+            comment_text(";;; Sums up.\n;;;\n;;;   indented\n  ;;;no space\n"),
+            "Sums up.\n\n  indented\nno space"
+        );
+        assert_eq!(
+            string_text("\"Sums \\\"all\\\" up.\n    @param x number\n      the value\n  \""),
+            "Sums \"all\" up.\n@param x number\n  the value\n"
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use tower_lsp::lsp_types::{Diagnostic, Position, Range};
+
+use crate::encoding::{encoding, LineIndex};
+use crate::skill::{parse_skill_chunks, Chunk};
+use crate::token::{Token, TokenKind, TokenScope};
+
+/// an open file kept in sync with the client, edits only reparse the chunks they touch
+#[derive(Debug, Clone)]
+pub struct Document {
+    pub text: String,
+    pub tokens: Vec<Token>,
+    pub errors: Vec<Diagnostic>,
+    /// empty while the text has syntax errors, every edit reparses all of it then
+    chunks: Vec<Chunk>,
+}
+
+/// byte offset of the start of every line
+fn line_starts(text: &str) -> Vec<usize> {
+    std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(at, _)| at + 1))
+        .collect()
+}
+
+fn shift_position(pos: &mut Position, lines: i64) {
+    pos.line = (pos.line as i64 + lines) as u32;
+}
+
+fn shift_range(range: &mut Range, lines: i64) {
+    shift_position(&mut range.start, lines);
+    shift_position(&mut range.end, lines);
+}
+
+fn shift_token(tok: &mut Token, lines: i64) {
+    shift_range(&mut tok.place, lines);
+    if let Some(encloses) = tok.encloses.as_mut() {
+        shift_range(encloses, lines);
+    }
+    for argument in tok.arguments.iter_mut() {
+        shift_range(argument.range_mut(), lines);
+    }
+    match &mut tok.scope {
+        TokenScope::Global(pos) => shift_position(pos, lines),
+        TokenScope::Local(range) => shift_range(range, lines),
+    }
+    if tok.kind == TokenKind::LetBlock {
+        let form = tok.name.split(':').next().unwrap_or("let").to_owned();
+        tok.name = format!("{}:{}", form, tok.place.start.line);
+    }
+}
+
+fn shift_chunk(chunk: &mut Chunk, lines: i64) {
+    chunk.first_line = (chunk.first_line as i64 + lines) as u32;
+    chunk.last_line = (chunk.last_line as i64 + lines) as u32;
+}
+
+impl Document {
+    pub fn new(text: String) -> Document {
+        let mut document = Document {
+            text,
+            tokens: vec![],
+            errors: vec![],
+            chunks: vec![],
+        };
+        document.reparse();
+        document
+    }
+
+    fn reparse(&mut self) {
+        match parse_skill_chunks(&self.text) {
+            Ok((tokens, chunks)) => {
+                self.tokens = tokens;
+                self.errors = vec![];
+                self.chunks = chunks;
+            }
+            Err(err) => {
+                self.tokens = vec![];
+                self.errors = vec![*err];
+                self.chunks = vec![];
+            }
+        }
+    }
+
+    /// replaces `range` with `text`, the whole document if there is no range
+    pub fn edit(&mut self, range: Option<Range>, text: &str) {
+        let Some(range) = range else {
+            self.text = text.to_owned();
+            self.reparse();
+            return;
+        };
+
+        let index = LineIndex::new(&self.text, encoding());
+        let from = index.offset(range.start);
+        let to = index.offset(range.end).max(from);
+        self.text.replace_range(from..to, text);
+
+        if self.chunks.is_empty() || !self.reparse_chunks(range, text) {
+            self.reparse();
+        }
+    }
+
+    /// reparses the chunks around an edit of `range` to `text`,
+    /// false if they do not parse on their own
+    fn reparse_chunks(&mut self, range: Range, text: &str) -> bool {
+        let delta = text.matches('\n').count() as i64 - (range.end.line - range.start.line) as i64;
+
+        // the lines around the edit as well, it might join them with its own
+        let touched_from = range.start.line.saturating_sub(1);
+        let touched_to = range.end.line + 1;
+        let mut first = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.last_line >= touched_from)
+            .unwrap_or(self.chunks.len());
+        let mut last = self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.first_line <= touched_to)
+            .map_or(0, |at| at + 1)
+            .max(first);
+
+        let mut first_line = range.start.line;
+        let mut last_line = range.end.line;
+        loop {
+            if first < last {
+                first_line = first_line.min(self.chunks[first].first_line);
+                last_line = last_line.max(self.chunks[last - 1].last_line);
+            }
+            // a docstring right above belongs to the form it documents
+            while first > 0
+                && self.chunks[first - 1].trailing_comment
+                && self.chunks[first - 1].last_line + 1 == first_line
+            {
+                first -= 1;
+                first_line = self.chunks[first].first_line;
+            }
+
+            let new_last_line = (last_line as i64 + delta) as u32;
+            let starts = line_starts(&self.text);
+            let from = starts
+                .get(first_line as usize)
+                .copied()
+                .unwrap_or(self.text.len());
+            let to = starts
+                .get(new_last_line as usize + 1)
+                .copied()
+                .unwrap_or(self.text.len());
+            let Ok((mut tokens, mut chunks)) = parse_skill_chunks(&self.text[from..to]) else {
+                return false;
+            };
+
+            let documents_next = chunks.last().is_some_and(|chunk| {
+                chunk.trailing_comment
+                    && self.chunks.get(last).is_some_and(|next| {
+                        next.first_line as i64 + delta == (chunk.last_line + first_line) as i64 + 1
+                    })
+            });
+            if documents_next {
+                last += 1;
+                continue;
+            }
+
+            tokens
+                .iter_mut()
+                .for_each(|tok| shift_token(tok, first_line as i64));
+            chunks
+                .iter_mut()
+                .for_each(|chunk| shift_chunk(chunk, first_line as i64));
+
+            let mut before: Vec<Token> = vec![];
+            let mut after: Vec<Token> = vec![];
+            for tok in self.tokens.drain(..) {
+                if tok.place.start.line < first_line {
+                    before.push(tok);
+                } else if tok.place.start.line > last_line {
+                    after.push(tok);
+                }
+            }
+            after.iter_mut().for_each(|tok| shift_token(tok, delta));
+            self.tokens = before.into_iter().chain(tokens).chain(after).collect();
+
+            let mut following = self.chunks.split_off(last);
+            following
+                .iter_mut()
+                .for_each(|chunk| shift_chunk(chunk, delta));
+            self.chunks.truncate(first);
+            self.chunks.extend(chunks);
+            self.chunks.extend(following);
+            return true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::skill::parse_skill_chunks;
+
+    use super::Document;
+
+    fn range(from: (u32, u32), to: (u32, u32)) -> Option<Range> {
+        Some(Range::new(
+            Position::new(from.0, from.1),
+            Position::new(to.0, to.1),
+        ))
+    }
+
+    /// applies `edits` one after another and checks against parsing the result at once
+    fn assert_incremental(text: &str, edits: &[(Option<Range>, &str)]) {
+        let mut document = Document::new(text.to_owned());
+        for (range, change) in edits {
+            document.edit(*range, change);
+
+            let full = Document::new(document.text.clone());
+            assert_eq!(
+                document.tokens, full.tokens,
+                "after {range:?} -> {change:?}"
+            );
+            assert_eq!(
+                document.errors, full.errors,
+                "after {range:?} -> {change:?}"
+            );
+            assert_eq!(
+                document.chunks, full.chunks,
+                "after {range:?} -> {change:?}"
+            );
+        }
+    }
+
+    const FILE: &str = "\
+;;; first
+a = 1
+b = list(a
+  2)
+
+procedure(fun(x)
+  let((y)
+    y = x + a
+  )
+)
+c = 3
+";
+
+    #[test]
+    fn edit_inside_form() {
+        assert_incremental(
+            FILE,
+            &[
+                (range((2, 9), (2, 10)), "bb"),
+                (range((7, 8), (7, 9)), "q"),
+                (range((10, 4), (10, 5)), "\"text\""),
+            ],
+        );
+    }
+
+    #[test]
+    fn edits_shifting_lines() {
+        assert_incremental(
+            FILE,
+            &[
+                (range((4, 0), (4, 0)), "d = 4\ne = d\n"),
+                (range((1, 0), (3, 4)), "a = 2"),
+                (range((0, 0), (0, 0)), "\n\n"),
+            ],
+        );
+    }
+
+    #[test]
+    fn edit_docstrings() {
+        assert_incremental(
+            FILE,
+            &[
+                (range((0, 4), (0, 9)), "changed"),
+                (range((9, 1), (9, 1)), "\n;;; about c"),
+                (range((0, 0), (1, 0)), ""),
+            ],
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let mut document = Document::new(FILE.to_owned());
+        document.edit(range((2, 4), (2, 4)), "(");
+        assert!(document.tokens.is_empty());
+        assert_eq!(document.errors.len(), 1);
+
+        assert_incremental(
+            FILE,
+            &[
+                (range((2, 4), (2, 4)), "("),
+                (range((2, 4), (2, 5)), ""),
+                (range((5, 10), (5, 10)), "\"open"),
+                (range((5, 10), (5, 15)), ""),
+            ],
+        );
+    }
+
+    #[test]
+    fn whole_document() {
+        assert_incremental(
+            FILE,
+            &[(None, "x = 1\n"), (range((1, 0), (1, 0)), "y = x\n")],
+        );
+    }
+
+    /// run with `cargo test --release -- --ignored --nocapture edit_benchmark`
+    #[test]
+    #[ignore]
+    fn edit_benchmark() {
+        let text: String = (0..5000)
+            .map(|line| format!("cell_var{line} = list(\"value\" {line} t)\n"))
+            .collect();
+        let mut document = Document::new(text);
+
+        let start = std::time::Instant::now();
+        for line in 0..100 {
+            document.edit(range((line * 40, 0), (line * 40, 4)), "pcell");
+        }
+        println!("100 edits: {:?}", start.elapsed());
+
+        let start = std::time::Instant::now();
+        parse_skill_chunks(&document.text).unwrap();
+        println!("full parse: {:?}", start.elapsed());
+    }
+}
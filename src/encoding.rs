@@ -0,0 +1,149 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use tower_lsp::lsp_types::{Position, PositionEncodingKind};
+
+/// unit `Position::character` counts in, as agreed on with the client
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf16,
+    Utf32,
+}
+
+/// negotiated in `initialize`, UTF-16 unless the client offers something else
+static ENCODING: AtomicU8 = AtomicU8::new(Encoding::Utf16 as u8);
+
+pub fn encoding() -> Encoding {
+    match ENCODING.load(Ordering::Relaxed) {
+        0 => Encoding::Utf8,
+        2 => Encoding::Utf32,
+        _ => Encoding::Utf16,
+    }
+}
+
+pub fn set_encoding(encoding: Encoding) {
+    ENCODING.store(encoding as u8, Ordering::Relaxed);
+}
+
+impl Encoding {
+    /// the first of the encodings a client offers, in its order of preference
+    pub fn negotiate(offered: Option<&[PositionEncodingKind]>) -> Encoding {
+        offered
+            .unwrap_or_default()
+            .iter()
+            .find_map(Encoding::from_kind)
+            .unwrap_or(Encoding::Utf16)
+    }
+
+    fn from_kind(kind: &PositionEncodingKind) -> Option<Encoding> {
+        match kind.as_str() {
+            "utf-8" => Some(Encoding::Utf8),
+            "utf-16" => Some(Encoding::Utf16),
+            "utf-32" => Some(Encoding::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn kind(self) -> PositionEncodingKind {
+        match self {
+            Encoding::Utf8 => PositionEncodingKind::UTF8,
+            Encoding::Utf16 => PositionEncodingKind::UTF16,
+            Encoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    pub fn width(self, c: char) -> u32 {
+        match self {
+            Encoding::Utf8 => c.len_utf8() as u32,
+            Encoding::Utf16 => c.len_utf16() as u32,
+            Encoding::Utf32 => 1,
+        }
+    }
+}
+
+/// converts between byte offsets and positions without scanning the text every time
+pub struct LineIndex<'t> {
+    text: &'t str,
+    /// byte offset of the start of every line
+    starts: Vec<usize>,
+    encoding: Encoding,
+}
+
+impl<'t> LineIndex<'t> {
+    pub fn new(text: &'t str, encoding: Encoding) -> LineIndex<'t> {
+        LineIndex {
+            text,
+            starts: std::iter::once(0)
+                .chain(text.match_indices('\n').map(|(at, _)| at + 1))
+                .collect(),
+            encoding,
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.starts[line]..offset]
+            .chars()
+            .map(|c| self.encoding.width(c))
+            .sum();
+        Position::new(line as u32, character)
+    }
+
+    /// the byte offset of `pos`, clamped to the end of its line
+    pub fn offset(&self, pos: Position) -> usize {
+        let Some(&start) = self.starts.get(pos.line as usize) else {
+            return self.text.len();
+        };
+        let mut units = 0;
+        for (at, c) in self.text[start..].char_indices() {
+            if units >= pos.character || c == '\n' {
+                return start + at;
+            }
+            units += self.encoding.width(c);
+        }
+        self.text.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, PositionEncodingKind};
+
+    use super::{Encoding, LineIndex};
+
+    #[test]
+    fn negotiate() {
+        assert_eq!(Encoding::negotiate(None), Encoding::Utf16);
+        assert_eq!(
+            Encoding::negotiate(Some(&[
+                PositionEncodingKind::new("utf-7"),
+                PositionEncodingKind::UTF8,
+                PositionEncodingKind::UTF16,
+            ])),
+            Encoding::Utf8
+        );
+    }
+
+    #[test]
+    fn positions_in_every_encoding() {
+        // µ takes two bytes, 𝄞 four bytes or two UTF-16 units
+        let text = "a = 1\n;;; 5µs 𝄞\nb = \"ü\"\n";
+        let clef_end = text.find('\n').unwrap() + 1 + ";;; 5µs 𝄞".len();
+        let b_string_end = text.rfind('"').unwrap() + 1;
+
+        for (encoding, clef, string) in [
+            (Encoding::Utf8, 13, 8),
+            (Encoding::Utf16, 10, 7),
+            (Encoding::Utf32, 9, 7),
+        ] {
+            let index = LineIndex::new(text, encoding);
+            assert_eq!(index.position(clef_end), Position::new(1, clef));
+            assert_eq!(index.position(b_string_end), Position::new(2, string));
+            assert_eq!(index.offset(Position::new(1, clef)), clef_end);
+            assert_eq!(index.offset(Position::new(2, string)), b_string_end);
+            assert_eq!(index.offset(Position::new(0, 99)), 5);
+            assert_eq!(index.offset(Position::new(9, 0)), text.len());
+        }
+    }
+}
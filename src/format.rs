@@ -0,0 +1,87 @@
+/// conversions of the format strings of `printf` and friends
+const CONVERSIONS: &str = "doxfegscnLPB";
+
+/// a `%` directive of a format string, each one takes an argument
+#[derive(Debug, PartialEq)]
+pub struct Directive {
+    /// `None` if the format string ends inside the directive
+    pub conversion: Option<char>,
+    /// byte offsets in the literal
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Directive {
+    pub fn is_known(&self) -> bool {
+        self.conversion.is_some_and(|c| CONVERSIONS.contains(c))
+    }
+}
+
+/// the argument of `name` holding the format string, counting positional arguments
+pub fn format_argument(name: &str) -> Option<usize> {
+    match name {
+        "printf" | "lsprintf" | "info" | "warn" | "error" => Some(0),
+        "fprintf" | "sprintf" => Some(1),
+        _ => None,
+    }
+}
+
+/// the directives of a string literal as written, `%%` is no directive
+pub fn directives(literal: &str) -> Vec<Directive> {
+    let mut directives = vec![];
+    let mut chars = literal.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '%' if chars.next_if(|(_, next)| *next == '%').is_some() => {}
+            '%' => {
+                // flags, width and precision
+                while chars
+                    .next_if(|(_, next)| "-+ #.".contains(*next) || next.is_ascii_digit())
+                    .is_some()
+                {}
+                let conversion = chars.next_if(|(_, next)| *next != '"');
+                let end = chars.peek().map_or(literal.len(), |(at, _)| *at);
+                directives.push(Directive {
+                    conversion: conversion.map(|(_, c)| c),
+                    start,
+                    end,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::directives;
+
+    #[test]
+    fn format_directives() {
+        let found: Vec<(Option<char>, bool, &str)> = {
+            let literal = r#""%d items, %-10s %5.2f%% \"%L\" %q %"#;
+            directives(literal)
+                .into_iter()
+                .map(|dir| (dir.conversion, dir.is_known(), &literal[dir.start..dir.end]))
+                .collect()
+        };
+        assert_eq!(
+            found,
+            vec![
+                (Some('d'), true, "%d"),
+                (Some('s'), true, "%-10s"),
+                (Some('f'), true, "%5.2f"),
+                (Some('L'), true, "%L"),
+                (Some('q'), false, "%q"),
+                (None, false, "%"),
+            ]
+        );
+        assert!(directives(r#""100%% done\n""#).is_empty());
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use crate::token::{Token, TokenKind, TokenScope};
+
+/// a global declaration together with the file it is made in
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub path: String,
+    pub token: Token,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    names: BTreeMap<String, Vec<Declaration>>,
+    /// names each file declares, to drop them once the file changes
+    files: HashMap<String, Vec<String>>,
+}
+
+/// global declarations of the whole workspace by name
+#[derive(Debug, Default)]
+pub struct SymbolIndex {
+    entries: RwLock<Entries>,
+}
+
+fn is_declaration(tok: &Token) -> bool {
+    matches!(
+        tok.kind,
+        TokenKind::VariableAssignment | TokenKind::Function
+    ) && matches!(tok.scope, TokenScope::Global(_))
+}
+
+/// scores how well `query` matches `candidate` as a case insensitive subsequence,
+/// consecutive characters and matches at word starts rank higher
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = candidate.char_indices();
+    let mut last_char = None;
+
+    for wanted in query.chars().map(|c| c.to_ascii_lowercase()) {
+        loop {
+            let (at, c) = chars.next()?;
+            let word_start = at == 0
+                || c.is_ascii_uppercase()
+                || last_char.is_some_and(|last: char| last == '_');
+            last_char = Some(c);
+            if c.to_ascii_lowercase() != wanted {
+                continue;
+            }
+
+            score += 1;
+            if previous.is_some_and(|prev| prev + 1 == at) {
+                score += 5;
+            }
+            if word_start {
+                score += 3;
+            }
+            previous = Some(at);
+            break;
+        }
+    }
+
+    Some(score * 100 - candidate.len() as i64)
+}
+
+impl SymbolIndex {
+    /// replaces the declarations of `path` with those found in `tokens`
+    pub fn update(&self, path: &str, tokens: &[Token]) {
+        let mut entries = self.entries.write().unwrap();
+        Self::drop_file(&mut entries, path);
+
+        let mut declared = vec![];
+        for tok in tokens.iter().filter(|tok| is_declaration(tok)) {
+            entries
+                .names
+                .entry(tok.name.to_owned())
+                .or_default()
+                .push(Declaration {
+                    path: path.to_owned(),
+                    token: tok.clone(),
+                });
+            declared.push(tok.name.to_owned());
+        }
+        entries.files.insert(path.to_owned(), declared);
+    }
+
+    pub fn remove(&self, path: &str) {
+        Self::drop_file(&mut self.entries.write().unwrap(), path);
+    }
+
+    fn drop_file(entries: &mut Entries, path: &str) {
+        for name in entries.files.remove(path).unwrap_or_default() {
+            if let Some(declarations) = entries.names.get_mut(&name) {
+                declarations.retain(|decl| decl.path != path);
+                if declarations.is_empty() {
+                    entries.names.remove(&name);
+                }
+            }
+        }
+    }
+
+    pub fn lookup(&self, name: &str) -> Vec<Declaration> {
+        self.entries
+            .read()
+            .unwrap()
+            .names
+            .get(name)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// declarations of up to `limit` names starting with `prefix`, in name order
+    pub fn prefixed(&self, prefix: &str, limit: usize) -> Vec<Declaration> {
+        self.entries
+            .read()
+            .unwrap()
+            .names
+            .range(prefix.to_owned()..)
+            .take_while(|(name, _)| name.starts_with(prefix))
+            .take(limit)
+            .flat_map(|(_, declarations)| declarations.iter().cloned())
+            .collect()
+    }
+
+    /// declarations of up to `limit` names fuzzily matching `query`, best match first
+    pub fn fuzzy(&self, query: &str, limit: usize) -> Vec<Declaration> {
+        let entries = self.entries.read().unwrap();
+        let mut matches: Vec<(i64, &String)> = entries
+            .names
+            .keys()
+            .filter_map(|name| fuzzy_score(query, name).map(|score| (score, name)))
+            .collect();
+        matches.sort_by(|(a, a_name), (b, b_name)| b.cmp(a).then(a_name.cmp(b_name)));
+
+        matches
+            .into_iter()
+            .take(limit)
+            .flat_map(|(_, name)| entries.names[name].iter().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::skill::parse_skill_content;
+
+    use super::{fuzzy_score, SymbolIndex};
+
+    fn names(declarations: Vec<super::Declaration>) -> Vec<String> {
+        declarations
+            .into_iter()
+            .map(|decl| decl.token.name)
+            .collect()
+    }
+
+    #[test]
+    fn prefix_lookup() {
+        let index = SymbolIndex::default();
+        let (tokens, _) = parse_skill_content(
+            "abcOpenCell = 1\nabcCloseCell = 2\nprocedure(abcOpen() t)\nxyzOpen = 3\n",
+        );
+        index.update("/pdk/a.il", &tokens);
+
+        assert_eq!(
+            names(index.prefixed("abcOpen", 10)),
+            vec!["abcOpen", "abcOpenCell"]
+        );
+        assert_eq!(names(index.prefixed("abc", 1)), vec!["abcCloseCell"]);
+        assert_eq!(names(index.lookup("xyzOpen")), vec!["xyzOpen"]);
+    }
+
+    #[test]
+    fn stale_entries_are_dropped() {
+        let index = SymbolIndex::default();
+        let (first, _) = parse_skill_content("shared = 1\nold = 2\n");
+        let (second, _) = parse_skill_content("shared = 3\n");
+        index.update("/pdk/a.il", &first);
+        index.update("/pdk/b.il", &second);
+
+        let (reparsed, _) = parse_skill_content("shared = 1\n");
+        index.update("/pdk/a.il", &reparsed);
+        assert!(index.lookup("old").is_empty());
+        assert_eq!(index.lookup("shared").len(), 2);
+
+        index.remove("/pdk/b.il");
+        assert_eq!(index.lookup("shared").len(), 1);
+        assert_eq!(index.lookup("shared")[0].path, "/pdk/a.il");
+    }
+
+    #[test]
+    fn fuzzy_lookup() {
+        assert!(fuzzy_score("ocv", "dbOpenCellViewByType").is_some());
+        assert!(fuzzy_score("ocx", "dbOpenCellViewByType").is_none());
+        assert!(fuzzy_score("open", "abcOpenCell") > fuzzy_score("open", "abcOptEntryNum"));
+
+        let index = SymbolIndex::default();
+        let (tokens, _) = parse_skill_content("abcOptEntryNum = 1\nabcOpenCell = 2\nxyz = 3\n");
+        index.update("/pdk/a.il", &tokens);
+        assert_eq!(
+            names(index.fuzzy("open", 10)),
+            vec!["abcOpenCell", "abcOptEntryNum"]
+        );
+    }
+}
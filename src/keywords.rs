@@ -0,0 +1,124 @@
+/// a syntax form of SKILL, these take their arguments unevaluated unlike functions
+#[derive(Debug, PartialEq)]
+pub struct Keyword {
+    pub name: &'static str,
+    /// how the form is written
+    pub form: &'static str,
+    pub description: &'static str,
+}
+
+static KEYWORDS: [Keyword; 17] = [
+    Keyword {
+        name: "let",
+        form: "let((x (y 1)) body...)",
+        description: "binds local variables for its body, unbound ones start as `nil`. the scope is dynamic in SKILL (`.il`), every procedure called from the body sees the bindings, and lexical only in SKILL++ (`.ils`)",
+    },
+    Keyword {
+        name: "prog",
+        form: "prog((x (y 1)) body...)",
+        description: "binds local variables like `let`, its body can leave early with `return` and jump with `go`",
+    },
+    Keyword {
+        name: "foreach",
+        form: "foreach(x l_list body...)",
+        description: "evaluates the body for every element, `foreach(mapcar x l_list ...)` collects the results",
+    },
+    Keyword {
+        name: "for",
+        form: "for(x x_from x_to body...)",
+        description: "evaluates the body for every integer from `x_from` to `x_to`",
+    },
+    Keyword {
+        name: "setof",
+        form: "setof(x l_list test)",
+        description: "the elements for which the test is not `nil`",
+    },
+    Keyword {
+        name: "exists",
+        form: "exists(x l_list test)",
+        description: "the rest of the list from the first element passing the test on",
+    },
+    Keyword {
+        name: "forall",
+        form: "forall(x l_list test)",
+        description: "whether every element passes the test",
+    },
+    Keyword {
+        name: "procedure",
+        form: "procedure(name(params...) body...)",
+        description: "defines a global procedure",
+    },
+    Keyword {
+        name: "lambda",
+        form: "lambda((params...) body...)",
+        description: "an anonymous procedure",
+    },
+    Keyword {
+        name: "if",
+        form: "if(test then else...)",
+        description: "evaluates `then` if the test is not `nil` and the rest otherwise",
+    },
+    Keyword {
+        name: "when",
+        form: "when(test body...)",
+        description: "evaluates the body if the test is not `nil`",
+    },
+    Keyword {
+        name: "unless",
+        form: "unless(test body...)",
+        description: "evaluates the body if the test is `nil`",
+    },
+    Keyword {
+        name: "cond",
+        form: "cond((test body...) ...)",
+        description: "evaluates the body of the first clause whose test is not `nil`",
+    },
+    Keyword {
+        name: "case",
+        form: "case(value (match body...) ... (t body...))",
+        description: "evaluates the body of the first clause matching the value, a list matches any of its elements",
+    },
+    Keyword {
+        name: "while",
+        form: "while(test body...)",
+        description: "evaluates the body as long as the test is not `nil`",
+    },
+    Keyword {
+        name: "return",
+        form: "return(@optional value)",
+        description: "leaves the enclosing `prog` with the value",
+    },
+    Keyword {
+        name: "quote",
+        form: "quote(expr)",
+        description: "the expression unevaluated, also written `'expr`",
+    },
+];
+
+/// the syntax form called `name`
+pub fn keyword(name: &str) -> Option<&'static Keyword> {
+    KEYWORDS.iter().find(|keyword| keyword.name == name)
+}
+
+impl Keyword {
+    pub fn markdown(&self) -> String {
+        format!(
+            "```lisp\n{}\n```\n\n*syntax form*\n\n---\n{}",
+            self.form, self.description
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::keyword;
+
+    #[test]
+    fn syntax_forms() {
+        let foreach = keyword("foreach").unwrap();
+        assert_eq!(foreach.form, "foreach(x l_list body...)");
+        assert!(foreach.markdown().starts_with("```lisp\nforeach("));
+        assert_eq!(keyword("printf"), None);
+        assert!(keyword("let").unwrap().description.contains("dynamic"));
+    }
+}
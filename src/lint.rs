@@ -0,0 +1,822 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde_json::Value;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticTag, Position, Range};
+
+use crate::builtins::builtin;
+use crate::deprecated::Deprecation;
+use crate::encoding::encoding;
+use crate::format::{directives, format_argument};
+
+use crate::rules::{
+    ARGUMENT_COUNT, DEPRECATED_FUNCTION, FORMAT_STRING, GLOBAL_PREFIX, IMPLICIT_GLOBAL,
+    SHADOWED_GLOBAL, TYPE_MISMATCH, UNCHECKED_KEYWORD, UNKNOWN_KEYWORD, UNUSED_VARIABLE,
+};
+use crate::signature::Signature;
+use crate::token::{Argument, Token, TokenKind, TokenScope};
+use crate::types::ValueType;
+
+/// the scope a local binding is visible in
+fn local_scope(tok: &Token) -> Option<Range> {
+    match tok.scope {
+        TokenScope::Local(range) if tok.kind == TokenKind::VariableAssignment => Some(range),
+        _ => None,
+    }
+}
+
+fn contains(range: &Range, other: &Range) -> bool {
+    range.start <= other.start && other.end <= range.end
+}
+
+/// the tokens binding local names together with their scope, later assignments
+/// to a bound name are local as well but bind nothing new
+fn local_bindings(tokens: &[Token]) -> Vec<(&Token, Range)> {
+    let mut bindings: Vec<(&Token, Range)> = vec![];
+    for tok in tokens {
+        if let Some(scope) = local_scope(tok) {
+            if !bindings
+                .iter()
+                .any(|(bound, bound_scope)| bound.name == tok.name && *bound_scope == scope)
+            {
+                bindings.push((tok, scope));
+            }
+        }
+    }
+    bindings
+}
+
+/// the ranges of every procedure definition
+fn procedure_bodies(tokens: &[Token]) -> Vec<Range> {
+    tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::Function)
+        .filter_map(|tok| tok.encloses)
+        .collect()
+}
+
+fn in_procedure(bodies: &[Range], place: &Range) -> bool {
+    bodies.iter().any(|body| contains(body, place))
+}
+
+/// names of global variables assigned outside of procedures, these are meant to be global
+pub fn declared_globals(tokens: &[Token]) -> impl Iterator<Item = &str> {
+    let bodies = procedure_bodies(tokens);
+    tokens
+        .iter()
+        .filter(move |tok| {
+            tok.kind == TokenKind::VariableAssignment
+                && matches!(tok.scope, TokenScope::Global(_))
+                && !in_procedure(&bodies, &tok.place)
+        })
+        .map(|tok| tok.name.as_str())
+}
+
+/// local bindings named like a global variable, with dynamic scoping every
+/// procedure called while the binding exists sees the local value instead
+pub fn shadowed_globals(tokens: &[Token], is_global: impl Fn(&str) -> bool) -> Vec<Diagnostic> {
+    local_bindings(tokens)
+        .into_iter()
+        .filter(|(tok, _)| is_global(&tok.name))
+        .map(|(tok, _)| {
+            SHADOWED_GLOBAL.diagnostic(
+                tok.place,
+                format!(
+                    "`{}` shadows a global variable, procedures called in its scope see the local value",
+                    tok.name
+                ),
+            )
+        })
+        .collect()
+}
+
+/// assignments in procedure bodies to names neither bound locally nor
+/// declared as global anywhere, these silently create a global variable
+pub fn implicit_globals(tokens: &[Token], is_global: impl Fn(&str) -> bool) -> Vec<Diagnostic> {
+    let bodies = procedure_bodies(tokens);
+    tokens
+        .iter()
+        .filter(|tok| {
+            tok.kind == TokenKind::VariableAssignment
+                && matches!(tok.scope, TokenScope::Global(_))
+                && in_procedure(&bodies, &tok.place)
+                && !is_global(&tok.name)
+        })
+        .map(|tok| {
+            IMPLICIT_GLOBAL.diagnostic(
+                tok.place,
+                format!(
+                    "assigning `{}` creates a global variable, bind it in `let` or `prog` to keep it local",
+                    tok.name
+                ),
+            )
+        })
+        .collect()
+}
+
+/// the name at the start of `tok.place`, assignments span all of `name = value`
+pub fn name_range(tok: &Token) -> Range {
+    let start = tok.place.start;
+    let width: u32 = tok.name.chars().map(|c| encoding().width(c)).sum();
+    Range::new(start, Position::new(start.line, start.character + width))
+}
+
+/// global variables and procedures defined without one of the allowed `prefixes`,
+/// the first definition of a name in the file is reported with the name as data
+pub fn unprefixed_globals(tokens: &[Token], prefixes: &[String]) -> Vec<Diagnostic> {
+    if prefixes.is_empty() {
+        return vec![];
+    }
+    let mut reported: Vec<&str> = vec![];
+
+    tokens
+        .iter()
+        .filter(|tok| {
+            matches!(
+                tok.kind,
+                TokenKind::VariableAssignment | TokenKind::Function
+            ) && matches!(tok.scope, TokenScope::Global(_))
+                && !prefixes.iter().any(|prefix| tok.name.starts_with(prefix))
+        })
+        .filter(|tok| {
+            let first = !reported.contains(&tok.name.as_str());
+            reported.push(&tok.name);
+            first
+        })
+        .map(|tok| {
+            let what = match tok.kind {
+                TokenKind::Function => "procedure",
+                _ => "global variable",
+            };
+            Diagnostic {
+                data: Some(Value::String(tok.name.clone())),
+                ..GLOBAL_PREFIX.diagnostic(
+                    name_range(tok),
+                    format!(
+                        "{what} `{}` does not start with {}",
+                        tok.name,
+                        prefixes
+                            .iter()
+                            .map(|prefix| format!("`{prefix}`"))
+                            .collect::<Vec<_>>()
+                            .join(" or ")
+                    ),
+                )
+            }
+        })
+        .collect()
+}
+
+/// places `name` refers to the global variable or procedure, inside a local
+/// binding of the same name it refers to that binding instead
+pub fn global_references(tokens: &[Token], name: &str) -> Vec<Range> {
+    let shadowing: Vec<Range> = local_bindings(tokens)
+        .into_iter()
+        .filter(|(tok, _)| tok.name == name)
+        .map(|(_, scope)| scope)
+        .collect();
+
+    tokens
+        .iter()
+        .filter(|tok| tok.name == name)
+        .filter(|tok| match tok.kind {
+            TokenKind::Function | TokenKind::FunctionCall => true,
+            TokenKind::VariableAssignment => matches!(tok.scope, TokenScope::Global(_)),
+            TokenKind::VariableUse => !shadowing.iter().any(|scope| contains(scope, &tok.place)),
+            _ => false,
+        })
+        .map(name_range)
+        .collect()
+}
+
+/// calls of deprecated functions, the replacement is kept as data
+pub fn deprecated_calls<'a>(
+    tokens: &[Token],
+    deprecation_of: impl Fn(&str) -> Option<&'a Deprecation>,
+) -> Vec<Diagnostic> {
+    tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::FunctionCall)
+        .filter_map(|call| {
+            let deprecation = deprecation_of(&call.name)?;
+            Some(Diagnostic {
+                tags: Some(vec![DiagnosticTag::DEPRECATED]),
+                // only a replacement taking the same arguments can be switched to
+                data: deprecation
+                    .replacement
+                    .clone()
+                    .filter(|_| deprecation.rename)
+                    .map(Value::String),
+                ..DEPRECATED_FUNCTION.diagnostic(call.place, deprecation.message(&call.name))
+            })
+        })
+        .collect()
+}
+
+fn plural(count: usize, what: &str) -> String {
+    match count {
+        1 => format!("1 {what}"),
+        _ => format!("{count} {what}s"),
+    }
+}
+
+/// calls passing the wrong number of positional arguments or unknown keyword
+/// arguments, keyword arguments ending up in `@rest` are only pointed out
+pub fn wrong_arguments(
+    tokens: &[Token],
+    signature_of: impl Fn(&str) -> Option<Signature>,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for call in tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::FunctionCall)
+    {
+        let Some(signature) = signature_of(&call.name) else {
+            continue;
+        };
+        let signature = signature.for_call(&call.arguments);
+        let positional = call
+            .arguments
+            .iter()
+            .filter(|arg| arg.is_positional())
+            .count();
+        let required = signature.required.len();
+        let max = signature.max_positional();
+        let expected = match max {
+            Some(max) if max == required => plural(required, "argument"),
+            Some(max) => format!("{required} to {max} arguments"),
+            None => format!("at least {}", plural(required, "argument")),
+        };
+        if positional < required || max.is_some_and(|max| positional > max) {
+            diagnostics.push(ARGUMENT_COUNT.diagnostic(
+                call.place,
+                format!("`{}` takes {expected} but got {positional}", call.name),
+            ));
+        }
+
+        for argument in call.arguments.iter() {
+            let Argument::Keyword(key, range) = argument else {
+                continue;
+            };
+            // only some keys of the built-in are known, the others may be fine
+            if signature.keys.contains(key) || signature.more_keys {
+                continue;
+            }
+            let (rule, message) = if !signature.keys.is_empty() {
+                (
+                    &UNKNOWN_KEYWORD,
+                    format!("`{}` has no keyword argument `?{key}`", call.name),
+                )
+            } else if signature.rest.is_some() {
+                (
+                    &UNCHECKED_KEYWORD,
+                    format!(
+                        "`?{key}` ends up in the @rest arguments of `{}` and cannot be checked",
+                        call.name
+                    ),
+                )
+            } else {
+                (
+                    &UNKNOWN_KEYWORD,
+                    format!("`{}` takes no keyword arguments", call.name),
+                )
+            };
+            diagnostics.push(rule.diagnostic(*range, message));
+        }
+    }
+
+    diagnostics
+}
+
+/// assignments by the name they assign
+pub fn assignments(tokens: &[Token]) -> HashMap<&str, Vec<&Token>> {
+    let mut assignments: HashMap<&str, Vec<&Token>> = HashMap::new();
+    for tok in tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::VariableAssignment)
+    {
+        assignments.entry(&tok.name).or_default().push(tok);
+    }
+    assignments
+}
+
+/// the type of the variable `used` from the last assignment in front of it,
+/// branches and loops are not followed
+pub fn variable_type(assignments: &HashMap<&str, Vec<&Token>>, used: &Token) -> Option<ValueType> {
+    assignments
+        .get(used.name.as_str())?
+        .iter()
+        .rfind(|tok| {
+            tok.place.start < used.place.start
+                && match tok.scope {
+                    TokenScope::Local(scope) => contains(&scope, &used.place),
+                    TokenScope::Global(_) => true,
+                }
+        })?
+        .value_type
+}
+
+/// calls passing a number where the parameter takes a string or the other way around,
+/// parameters are typed by the prefixes of the Cadence reference like `t_string`
+pub fn type_mismatches(
+    tokens: &[Token],
+    signature_of: impl Fn(&str) -> Option<Signature>,
+) -> Vec<Diagnostic> {
+    let assignments = assignments(tokens);
+    let uses: BTreeMap<Position, &Token> = tokens
+        .iter()
+        .filter(|tok| matches!(tok.kind, TokenKind::VariableUse | TokenKind::FunctionCall))
+        .map(|tok| (tok.place.start, tok))
+        .collect();
+    let argument_type = |argument: &Argument| match argument {
+        Argument::String(..) => Some(ValueType::String),
+        Argument::Typed(value_type, _) => Some(*value_type),
+        Argument::Positional(range) => {
+            let (_, first) = uses.range(range.start..range.end).next()?;
+            match first.kind {
+                TokenKind::VariableUse if first.place == *range => {
+                    variable_type(&assignments, first)
+                }
+                // `f(x)` or `(f x)`, a call returning what its docstring declares
+                TokenKind::FunctionCall
+                    if first.place.start.line == range.start.line
+                        && first.place.start.character <= range.start.character + 1 =>
+                {
+                    signature_of(&first.name)?.returns
+                }
+                _ => None,
+            }
+        }
+        Argument::Keyword(..) => None,
+    };
+    let mut diagnostics = vec![];
+
+    for call in tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::FunctionCall)
+    {
+        let Some(signature) = signature_of(&call.name) else {
+            continue;
+        };
+        let signature = signature.for_call(&call.arguments);
+        let positional = call.arguments.iter().filter(|arg| arg.is_positional());
+
+        for (argument, parameter) in positional.zip(signature.positional()) {
+            let (Some(given), Some(expected)) =
+                (argument_type(argument), signature.parameter_type(parameter))
+            else {
+                continue;
+            };
+            if given.mismatches(expected) {
+                let mut argument = argument.clone();
+                diagnostics.push(TYPE_MISMATCH.diagnostic(
+                    *argument.range_mut(),
+                    format!(
+                        "`{}` takes a {} as `{parameter}` but is given a {}",
+                        call.name,
+                        expected.name(),
+                        given.name()
+                    ),
+                ));
+            }
+        }
+    }
+
+    diagnostics
+}
+
+/// where the bytes `from..to` of text starting at `start` are
+fn range_in(text: &str, start: Position, from: usize, to: usize) -> Range {
+    let mut pos = start;
+    let mut positions = vec![];
+    for (at, c) in text.char_indices() {
+        if at == from {
+            positions.push(pos);
+        }
+        if at == to {
+            break;
+        }
+        match c {
+            '\n' => pos = Position::new(pos.line + 1, 0),
+            _ => pos.character += encoding().width(c),
+        }
+    }
+    let start = positions.first().copied().unwrap_or(pos);
+    Range::new(start, pos)
+}
+
+/// format strings of `printf` and friends with unknown directives or
+/// a different number of directives than arguments
+pub fn format_mismatches(tokens: &[Token]) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+
+    for call in tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::FunctionCall)
+    {
+        let Some(mut at) = format_argument(&call.name) else {
+            continue;
+        };
+        // `error('myFn "format" ...)` names the failing function first
+        if builtin(&call.name)
+            .is_some_and(|builtin| builtin.signature.binds_leading(&call.arguments))
+        {
+            at += 1;
+        }
+        let positional: Vec<&Argument> = call
+            .arguments
+            .iter()
+            .filter(|arg| arg.is_positional())
+            .collect();
+        let Some(Argument::String(literal, place)) = positional.get(at) else {
+            continue;
+        };
+
+        let directives = directives(literal);
+        for directive in directives.iter().filter(|directive| !directive.is_known()) {
+            let text = &literal[directive.start..directive.end];
+            diagnostics.push(FORMAT_STRING.diagnostic(
+                range_in(literal, place.start, directive.start, directive.end),
+                format!("unknown format directive `{text}`"),
+            ));
+        }
+
+        let given = positional.len() - at - 1;
+        if directives.len() != given {
+            diagnostics.push(FORMAT_STRING.diagnostic(
+                *place,
+                format!(
+                    "the format takes {} but `{}` is given {given}",
+                    plural(directives.len(), "argument"),
+                    call.name
+                ),
+            ));
+        }
+    }
+
+    diagnostics
+}
+
+/// `let`, `prog` and loop bindings and procedure parameters that are never read,
+/// names starting with `_` are left alone
+pub fn unused_variables(tokens: &[Token]) -> Vec<Diagnostic> {
+    let bindings = local_bindings(tokens);
+    let bodies = procedure_bodies(tokens);
+    let mut read = vec![false; bindings.len()];
+
+    for used in tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::VariableUse)
+    {
+        // the innermost binding of the name is the one that is read
+        let binding = bindings
+            .iter()
+            .enumerate()
+            .filter(|(_, (tok, scope))| tok.name == used.name && contains(scope, &used.place))
+            .max_by_key(|(_, (_, scope))| scope.start);
+        if let Some((at, _)) = binding {
+            read[at] = true;
+        }
+    }
+
+    bindings
+        .iter()
+        .zip(read)
+        .filter(|((tok, _), read)| !read && !tok.name.starts_with('_'))
+        .map(|((tok, scope), _)| {
+            let is_parameter = bodies.contains(scope);
+            let what = if is_parameter {
+                "parameter"
+            } else {
+                "variable"
+            };
+            Diagnostic {
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                ..UNUSED_VARIABLE.diagnostic(tok.place, format!("unused {what} `{}`", tok.name))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::skill::parse_skill_content;
+
+    use crate::builtins::builtin;
+    use crate::deprecated::Deprecation;
+    use crate::docstring::Docstring;
+    use crate::signature::Signature;
+
+    use super::{
+        declared_globals, deprecated_calls, format_mismatches, global_references, implicit_globals,
+        shadowed_globals, type_mismatches, unprefixed_globals, unused_variables, wrong_arguments,
+    };
+
+    fn unused(code: &str) -> Vec<String> {
+        let (tokens, errors) = parse_skill_content(code);
+        assert!(errors.is_empty());
+        unused_variables(&tokens)
+            .into_iter()
+            .map(|diag| diag.message)
+            .collect()
+    }
+
+    #[test]
+    fn unused_bindings() {
+        assert_eq!(
+            unused("let((a b (c 1)) a = c)\nprog((d) return(nil))\n"),
+            vec![
+                "unused variable `a`",
+                "unused variable `b`",
+                "unused variable `d`"
+            ]
+        );
+        assert_eq!(
+            unused("procedure(fun(x y @optional (z 1) @rest _more) list(y))\n"),
+            vec!["unused parameter `x`", "unused parameter `z`"]
+        );
+        assert_eq!(
+            unused("foreach(cell cells t)\nforeach(mapcar item items item)\nfor(_i 0 3 t)\n"),
+            vec!["unused variable `cell`"]
+        );
+    }
+
+    #[test]
+    fn inner_bindings_shadow() {
+        assert_eq!(unused("let((x) let((x) x))\n"), vec!["unused variable `x`"]);
+        assert_eq!(
+            unused("let((x) let((y) x))\nlet(((z 1) (w z)) w)\n"),
+            vec!["unused variable `y`"]
+        );
+    }
+
+    #[test]
+    fn dynamic_scope_hazards() {
+        let (globals, _) =
+            parse_skill_content("verbose = nil\nprocedure(log(msg) when(verbose printf(msg)))\n");
+        let (tokens, _) = parse_skill_content(
+            "procedure(run(verbose)\n  let((count)\n    count = 1\n    total = count\n    verbose = t\n  )\n  log(\"done\")\n)\n",
+        );
+        let globals: Vec<&str> = declared_globals(&globals).collect();
+        assert_eq!(globals, vec!["verbose"]);
+        let is_global = |name: &str| globals.contains(&name);
+
+        let messages = |diags: Vec<tower_lsp::lsp_types::Diagnostic>| {
+            diags
+                .into_iter()
+                .map(|diag| (diag.range.start.line, diag.message))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            messages(shadowed_globals(&tokens, is_global)),
+            vec![(0, "`verbose` shadows a global variable, procedures called in its scope see the local value".to_string())]
+        );
+        assert_eq!(
+            messages(implicit_globals(&tokens, is_global)),
+            vec![(3, "assigning `total` creates a global variable, bind it in `let` or `prog` to keep it local".to_string())]
+        );
+    }
+
+    #[test]
+    fn call_arguments() {
+        let (tokens, errors) = parse_skill_content(
+            "procedure(area(w h @optional (unit \"um\")) times(w h))\n\
+             procedure(field(@key name prompt) list(name prompt))\n\
+             area(1)\narea(1 2 \"nm\" 4)\n(area 1 2)\n\
+             field(?name \"a\" ?size 3)\narea(1 2 ?unit \"nm\")\n\
+             printf()\nprintf(\"%d\" 1 ?stream t)\ncar(x y)\nunknownFun(1 2 3)\n\
+             hiCreateButton(?name 'b ?buttonText \"Go\" ?enabled nil ?toolTip \"go\")\n\
+             error('myFn \"failed %d\" 3)\nerror('myFn)\n",
+        );
+        assert!(errors.is_empty());
+        let signature_of = |name: &str| {
+            tokens
+                .iter()
+                .find(|tok| tok.kind == crate::token::TokenKind::Function && tok.name == name)
+                .and_then(|tok| Signature::parse(tok.info.as_deref()?))
+                .or_else(|| builtin(name).map(|builtin| builtin.signature.clone()))
+        };
+
+        let found: Vec<(u32, String)> = wrong_arguments(&tokens, signature_of)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, "`area` takes 2 to 3 arguments but got 1".to_string()),
+                (3, "`area` takes 2 to 3 arguments but got 4".to_string()),
+                (5, "`field` has no keyword argument `?size`".to_string()),
+                (6, "`area` takes no keyword arguments".to_string()),
+                (
+                    7,
+                    "`printf` takes at least 1 argument but got 0".to_string()
+                ),
+                (
+                    8,
+                    "`?stream` ends up in the @rest arguments of `printf` and cannot be checked"
+                        .to_string()
+                ),
+                (9, "`car` takes 1 argument but got 2".to_string()),
+                (
+                    13,
+                    "`error` takes at least 2 arguments but got 1".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn global_prefixes() {
+        let (tokens, errors) = parse_skill_content(
+            "ACMEdebug = nil\ncounter = 0\ncounter = 1\n\
+             procedure(ACMEcount() counter = counter + 1)\n\
+             procedure(helper(counter) let((step) step = counter))\nhelper(counter)\n",
+        );
+        assert!(errors.is_empty());
+
+        let found: Vec<(u32, String)> = unprefixed_globals(&tokens, &["ACME".to_string()])
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    "global variable `counter` does not start with `ACME`".to_string()
+                ),
+                (
+                    4,
+                    "procedure `helper` does not start with `ACME`".to_string()
+                ),
+            ]
+        );
+        assert!(unprefixed_globals(&tokens, &[]).is_empty());
+
+        let places: Vec<(u32, u32, u32)> = global_references(&tokens, "counter")
+            .into_iter()
+            .map(|range| (range.start.line, range.start.character, range.end.character))
+            .collect();
+        assert_eq!(
+            places,
+            vec![(1, 0, 7), (2, 0, 7), (3, 22, 29), (3, 32, 39), (5, 7, 14)]
+        );
+    }
+
+    #[test]
+    fn deprecated_functions() {
+        let (tokens, errors) = parse_skill_content(
+            "cv = dbOpenCellView(\"lib\" \"cell\" \"layout\")\nACMEold(cv)\ndbOpenCellViewByType(\"lib\" \"cell\" \"layout\")\n\
+             cv = geGetEditRep()\n",
+        );
+        assert!(errors.is_empty());
+        let in_house = Deprecation {
+            replacement: Some("ACMEnew".to_string()),
+            rename: true,
+            ..Default::default()
+        };
+        let deprecation_of = |name: &str| match name {
+            "ACMEold" => Some(&in_house),
+            _ => crate::deprecated::deprecation(name),
+        };
+
+        let found: Vec<(u32, Option<serde_json::Value>)> =
+            deprecated_calls(&tokens, deprecation_of)
+                .into_iter()
+                .map(|diag| (diag.range.start.line, diag.data))
+                .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, None),
+                (1, Some(serde_json::json!("ACMEnew"))),
+                // the shipped data renames calls as well, the quick fix is built from this
+                (3, Some(serde_json::json!("geGetEditCellView")))
+            ]
+        );
+    }
+
+    #[test]
+    fn format_strings() {
+        let (tokens, errors) = parse_skill_content(
+            "printf(\"%d cells\\n\" length(cells))\nprintf(\"%s is %d\\n\" name)\n\
+             sprintf(nil \"%L\" x y)\nfprintf(port \"%5.2f%%\\n\" 0.5)\n\
+             warn(\"%q µ %n\" 1 2)\ninfo(fmt 1 2)\nerror(\"done\\n\")\n\
+             error('myFn \"%s failed\\n\" name)\nerror('myFn \"%s failed\\n\")\n",
+        );
+        assert!(errors.is_empty());
+
+        let found: Vec<(u32, u32, String)> = format_mismatches(&tokens)
+            .into_iter()
+            .map(|diag| {
+                (
+                    diag.range.start.line,
+                    diag.range.start.character,
+                    diag.message,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    7,
+                    "the format takes 2 arguments but `printf` is given 1".to_string()
+                ),
+                (
+                    2,
+                    12,
+                    "the format takes 1 argument but `sprintf` is given 2".to_string()
+                ),
+                (4, 6, "unknown format directive `%q`".to_string()),
+                (
+                    8,
+                    12,
+                    "the format takes 1 argument but `error` is given 0".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn obvious_type_mismatches() {
+        let (tokens, errors) = parse_skill_content(
+            "name = \"cell\"\ncount = length(cells)\ntotal = name + 1\n\
+             label = strcat(name count)\nstrcat(\"n=\" 3.5 name)\n\
+             let(((name 2)) twice = name * 2)\nscale = plus(1 2.0) * 2\nstrcat(scale)\n",
+        );
+        assert!(errors.is_empty());
+        let signature_of = |name: &str| builtin(name).map(|builtin| builtin.signature.clone());
+
+        let found: Vec<(u32, String)> = type_mismatches(&tokens, signature_of)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    2,
+                    "`plus` takes a number as `n_number` but is given a string".to_string()
+                ),
+                (
+                    3,
+                    "`strcat` takes a string as `t_strings` but is given a fixnum".to_string()
+                ),
+                (
+                    4,
+                    "`strcat` takes a string as `t_strings` but is given a flonum".to_string()
+                ),
+                (
+                    7,
+                    "`strcat` takes a string as `t_string` but is given a number".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn docstring_types() {
+        let (tokens, errors) = parse_skill_content(
+            "procedure(area(w h) times(w h))
+area(\"2\" 3)
+strcat(area(2 3))
+             (strcat (area 2 3))
+strcat(\"x\" area)
+",
+        );
+        assert!(errors.is_empty());
+        let signature_of = |name: &str| {
+            let mut signature = match name {
+                "area" => Signature::parse("area(w h)")?,
+                _ => return builtin(name).map(|builtin| builtin.signature.clone()),
+            };
+            Docstring::parse("@param w number\n@return number").annotate(&mut signature);
+            Some(signature)
+        };
+
+        let found: Vec<(u32, String)> = type_mismatches(&tokens, signature_of)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    1,
+                    "`area` takes a number as `w` but is given a string".to_string()
+                ),
+                (
+                    2,
+                    "`strcat` takes a string as `t_string` but is given a number".to_string()
+                ),
+                (
+                    3,
+                    "`strcat` takes a string as `t_string` but is given a number".to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -1,34 +1,154 @@
+mod builtins;
+use builtins::{builtin, Builtin};
+
 mod cache;
 
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
 use cache::TokenCache;
 
+mod config;
+use config::Config;
+
+mod deprecated;
+
+mod docstring;
+use docstring::{Docstring, Tag};
+
+mod format;
+
+mod keywords;
+use keywords::{keyword, Keyword};
+
+mod signature;
+use signature::{call_at, Signature};
+
 mod skill;
 use dashmap::DashMap;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use token::TokenKind;
+use token::{Token, TokenKind, TokenScope};
 use tower_lsp::jsonrpc::{Error, ErrorCode, Result};
 use tower_lsp::lsp_types::notification::Notification;
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
 
 use log::{debug, info};
-use walkdir::WalkDir;
+
+mod lint;
+use lint::{
+    assignments, declared_globals, deprecated_calls, format_mismatches, global_references,
+    implicit_globals, name_range, shadowed_globals, type_mismatches, unprefixed_globals,
+    unused_variables, variable_type, wrong_arguments,
+};
+
+mod persist;
+use persist::{index_file, DiskCache};
+
+mod document;
+use document::Document;
+
+mod encoding;
+use encoding::{encoding, set_encoding, Encoding, LineIndex};
+
+mod index;
+
+mod progress;
+use progress::Progress;
+
+mod rules;
+use rules::{configure, is_rule, rule, suppress, LintRule, DEPRECATED_FUNCTION, GLOBAL_PREFIX};
 
 mod token;
 
+mod types;
+use types::{number_value, ValueType};
+
+mod uri;
+use uri::{key_to_uri, uri_to_key, uri_to_path};
+
+mod workspace;
+use workspace::{
+    is_skill_file, owning_folder, search_dirs, skill_file_globs, Exports, Folder, LoadGraph,
+};
+
 extern crate glob;
 extern crate pest;
 extern crate regex;
 #[macro_use]
 extern crate pest_derive;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct Backend {
     client: Client,
-    cache: TokenCache,
-    diags: DashMap<String, Vec<Diagnostic>>,
+    cache: Arc<TokenCache>,
+    diags: Arc<DashMap<String, Vec<Diagnostic>>>,
+    /// files open in the client, their text replaces the one on disk
+    documents: Arc<DashMap<String, Document>>,
+    folders: Arc<RwLock<Vec<Folder>>>,
+    /// settings from `initializationOptions` or the last pushed configuration
+    settings: Arc<RwLock<Option<Value>>>,
+    /// whether the client answers `workspace/configuration` requests
+    pull_configuration: Arc<AtomicBool>,
+    /// whether the client lets us register file watchers
+    watch_files: Arc<AtomicBool>,
+    /// whether the client shows `$/progress` reports
+    report_progress: Arc<AtomicBool>,
+    /// latest indexing run that was started, bumping it cancels the running one
+    indexing: Arc<AtomicUsize>,
+    /// latest indexing run that finished
+    indexed: Arc<AtomicUsize>,
+    /// what diagnostics are checked against, dropped when the exports of a file change
+    lint: Arc<RwLock<Option<Arc<LintContext>>>>,
+    /// how often the context got dropped, one built in the meantime is outdated
+    lint_changes: Arc<AtomicUsize>,
+    /// edited files whose diagnostics are not published yet
+    edited: Arc<Mutex<HashSet<String>>>,
+    /// latest edit, a pending diagnostics run gives way to a newer one
+    edits: Arc<AtomicUsize>,
+}
+
+/// the load graph and the globals of the whole workspace
+#[derive(Debug)]
+struct LintContext {
+    graph: LoadGraph,
+    globals: HashSet<String>,
+    procedures: HashMap<String, Signature>,
+}
+
+impl LintContext {
+    fn new(cache: &TokenCache, folders: &[Folder]) -> LintContext {
+        let graph = LoadGraph::resolve(cache, &|path| search_dirs(folders, path));
+        let mut globals = HashSet::new();
+        let mut procedures = HashMap::new();
+        for entry in cache.symbols.iter() {
+            globals.extend(declared_globals(entry.value()).map(str::to_owned));
+            for tok in entry.value().iter() {
+                if tok.kind == TokenKind::Function && !procedures.contains_key(&tok.name) {
+                    if let Some(signature) = procedure_signature(tok) {
+                        procedures.insert(tok.name.clone(), signature);
+                    }
+                }
+            }
+        }
+        LintContext {
+            graph,
+            globals,
+            procedures,
+        }
+    }
+
+    fn signature_of(&self, name: &str) -> Option<Signature> {
+        self.procedures
+            .get(name)
+            .cloned()
+            .or_else(|| builtin(name).map(|builtin| builtin.signature.clone()))
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -54,57 +174,678 @@ impl Notification for CustomNotification {
     const METHOD: &'static str = "custom/notification";
 }
 
+/// most global names offered by a single completion or workspace symbol request
+const COMPLETION_LIMIT: usize = 200;
+
+/// how long edits have to pause before their diagnostics get computed
+const DIAGNOSTICS_DELAY: Duration = Duration::from_millis(200);
+
+/// the part of the identifier in front of `pos`
+fn identifier_before(text: &str, pos: Position) -> String {
+    let index = LineIndex::new(text, encoding());
+    let line_start = index.offset(Position::new(pos.line, 0));
+    let before = &text[line_start..index.offset(pos)];
+    let start = before
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |at| at + 1);
+    before[start..].to_owned()
+}
+
+/// the signature of a procedure definition with the types its docstring declares
+fn procedure_signature(tok: &Token) -> Option<Signature> {
+    let mut signature = Signature::parse(tok.info.as_deref()?)?;
+    if let Some(doc) = &tok.documentation {
+        Docstring::parse(doc).annotate(&mut signature);
+    }
+    Some(signature)
+}
+
+/// hover text of a declaration, linking to where it is
+fn declaration_markdown(tok: &Token, path: &str, value_type: Option<ValueType>) -> String {
+    let typed = value_type.map_or(String::new(), |value_type| {
+        format!(": `{}`", value_type.name())
+    });
+    let mut parts = vec![format!("*{}* **{}**{typed}", tok.scope.value(), tok.name)];
+    if let Some(info) = &tok.info {
+        parts.push(format!("```lisp\n{info}\n```"));
+    }
+    let line = tok.place.start.line + 1;
+    let file = Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path);
+    parts.push(match key_to_uri(path) {
+        Some(uri) => format!("*declared in [{file}:{line}]({uri}#L{line})*"),
+        None => format!("*declared in {file}:{line}*"),
+    });
+    if let Some(doc) = &tok.documentation {
+        parts.push(format!("---\n{}", Docstring::parse(doc).markdown()));
+    }
+    parts.join("\n\n")
+}
+
+/// hover text of a declaration, parameters are described by their procedure's docstring
+fn declaration_hover(
+    tokens: &[Token],
+    declaration: &Token,
+    path: &str,
+    value_type: Option<ValueType>,
+) -> String {
+    let procedure = match declaration.scope {
+        TokenScope::Local(scope) => tokens.iter().find(|tok| {
+            tok.kind == TokenKind::Function
+                && tok.encloses == Some(scope)
+                && procedure_signature(tok)
+                    .is_some_and(|signature| signature.parameters().contains(&&declaration.name))
+        }),
+        TokenScope::Global(_) => None,
+    };
+    let Some(procedure) = procedure else {
+        return declaration_markdown(declaration, path, value_type);
+    };
+
+    let value_type =
+        value_type.or_else(|| procedure_signature(procedure)?.parameter_type(&declaration.name));
+    let tag = procedure
+        .documentation
+        .as_deref()
+        .map(Docstring::parse)
+        .and_then(|doc| doc.param(&declaration.name).map(Tag::markdown))
+        .unwrap_or_default();
+    format!(
+        "{}\n\n---\nparameter of `{}`{tag}",
+        declaration_markdown(declaration, path, value_type),
+        procedure.name
+    )
+}
+
+/// hover text of what has no token, numbers, `~>` attributes and syntax forms
+fn text_hover(text: &str, at: Position) -> Option<String> {
+    let offset = LineIndex::new(text, encoding()).offset(at);
+    let word_at = |is_word: fn(char) -> bool| {
+        let start = text[..offset]
+            .rfind(|c: char| !is_word(c))
+            .map_or(0, |before| before + 1);
+        let end = text[offset..]
+            .find(|c: char| !is_word(c))
+            .map_or(text.len(), |after| offset + after);
+        (start, end)
+    };
+
+    // the rules named in a suppression comment
+    let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let before = &text[line_start..offset];
+    if before
+        .find(';')
+        .is_some_and(|comment| before[comment..].contains("srls-"))
+    {
+        let (start, end) = word_at(|c| c.is_ascii_alphanumeric() || c == '-');
+        return rule(&text[start..end]).map(LintRule::markdown);
+    }
+
+    let (start, end) = word_at(|c| c.is_alphanumeric() || "_.%".contains(c));
+    let word = &text[start..end];
+    if word.is_empty() {
+        return None;
+    }
+
+    if let (Some(value), Some(value_type)) = (number_value(word), ValueType::of_literal(word)) {
+        return Some(format!(
+            "*number* **{word}**: `{}`\n\n= `{value:?}`",
+            value_type.name()
+        ));
+    }
+    for (operator, what) in [("~>", "attribute"), ("->", "property")] {
+        if let Some(object) = text[..start].strip_suffix(operator) {
+            let object_start = object
+                .rfind(|c: char| !c.is_alphanumeric() && !"_.%".contains(c))
+                .map_or(0, |before| before + 1);
+            return Some(format!(
+                "*{what}* **{word}** of `{}`",
+                &object[object_start..]
+            ));
+        }
+    }
+    keyword(word).map(Keyword::markdown)
+}
+
 fn pos_in_range(pos: &Position, range: &Range) -> bool {
-    ((pos.line > range.start.line) && (pos.line < range.end.line))
-        || (pos.line == range.start.line && pos.character >= range.start.character)
-        || (pos.line == range.end.line && pos.character <= range.end.character)
+    range.start <= *pos && *pos <= range.end
 }
 
 async fn update_diagnostics(client: &Client, for_file: &str, diagnostics: Vec<Diagnostic>) {
-    client
-        .publish_diagnostics(
-            tower_lsp::lsp_types::Url::parse(("file://".to_owned() + for_file).as_str()).unwrap(),
-            diagnostics,
-            None,
-        )
-        .await;
+    match key_to_uri(for_file) {
+        Some(uri) => client.publish_diagnostics(uri, diagnostics, None).await,
+        None => info!("no URI for {:?}", for_file),
+    }
 }
 
-#[tower_lsp::async_trait]
-impl LanguageServer for Backend {
-    async fn initialize(&self, init_params: InitializeParams) -> Result<InitializeResult> {
-        let root = init_params
-            .root_uri
-            .ok_or(Error::new(ErrorCode::InvalidParams))?;
-        info!(target: "Backend", "Initializing Language Server");
-
-        let root_dir = root.path().to_string();
+fn quick_fix(
+    title: String,
+    diagnostic: &Diagnostic,
+    changes: HashMap<Url, Vec<TextEdit>>,
+    is_preferred: bool,
+) -> CodeActionOrCommand {
+    CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(vec![diagnostic.clone()]),
+        edit: Some(WorkspaceEdit::new(changes)),
+        is_preferred: Some(is_preferred),
+        ..Default::default()
+    })
+}
 
-        info!(target: "Backend", "Caching started in '{}'", root_dir);
+impl Backend {
+    /// SKILL files of every folder that are not cached yet
+    fn unindexed_files(&self) -> Vec<String> {
+        let folders = self.folders.read().unwrap().clone();
+        let mut files = vec![];
+        let mut found = HashSet::new();
 
-        for entry in WalkDir::new(root_dir)
-            .follow_links(true)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let f_path = entry.path().to_str();
-            match f_path {
-                Some(path) => {
-                    if path.ends_with(".il") {
-                        info!("found '{}'", path);
-                        let (_, parsed_errors) = self.cache.update(path);
-                        self.diags.insert(path.to_owned(), parsed_errors);
-                    }
+        for folder in folders {
+            info!(target: "Backend", "Caching started in '{}'", folder.root.display());
+            for path in folder.skill_files() {
+                if !self.cache.symbols.contains_key(&path) && found.insert(path.clone()) {
+                    info!("found '{}'", path);
+                    files.push(path);
                 }
-                None => {}
             }
         }
+
+        files
+    }
+
+    /// indexes new files in the background, a newer run cancels the current one
+    fn spawn_indexing(&self) {
+        let generation = self.indexing.fetch_add(1, Ordering::SeqCst) + 1;
+        let backend = self.clone();
+        tokio::spawn(async move { backend.index_workspace(generation).await });
+    }
+
+    async fn index_workspace(&self, generation: usize) {
+        let files = self.unindexed_files();
+        let mut progress = if self.report_progress.load(Ordering::Relaxed) {
+            Progress::begin(
+                &self.client,
+                &format!("srls-indexing-{generation}"),
+                "Indexing SKILL files",
+            )
+            .await
+        } else {
+            None
+        };
+
+        let total = files.len();
+        let stored = self
+            .folders
+            .read()
+            .unwrap()
+            .iter()
+            .filter_map(|folder| index_file(&folder.root))
+            .fold(DiskCache::default(), |mut stored, file| {
+                stored.extend(DiskCache::load(&file));
+                stored
+            });
+        let (parsed, mut parsed_files) = tokio::sync::mpsc::unbounded_channel();
+        let cache = self.cache.clone();
+        let indexing = self.indexing.clone();
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let pool = tokio::task::spawn_blocking(move || {
+            cache.update_all(
+                &files,
+                &stored,
+                workers,
+                || indexing.load(Ordering::SeqCst) != generation,
+                |path| {
+                    let _ = parsed.send(path.to_owned());
+                },
+            )
+        });
+
+        let mut done = 0;
+        while parsed_files.recv().await.is_some() {
+            done += 1;
+            if let Some(progress) = progress.as_mut() {
+                progress.report(done, total).await;
+            }
+        }
+
+        if !pool.await.unwrap_or(false) {
+            info!(target: "Backend", "Caching cancelled after {} files.", done);
+            if let Some(progress) = progress {
+                progress.end("cancelled").await;
+            }
+            return;
+        }
+
         info!(target: "Backend", "Caching finished. Found {} files.", self.cache.symbols.len());
         debug!(target: "Backend", "{:?}", self.cache.symbols);
+        if let Some(progress) = progress {
+            progress.end(format!("{total} files")).await;
+        }
+
+        self.indexed.store(generation, Ordering::SeqCst);
+        let removed = self.prune();
+        self.save_index();
+        self.publish_diagnostics(removed).await;
+    }
+
+    /// parses `path` from disk again and tells the user if that failed,
+    /// returns the path if the file is gone and got dropped
+    async fn reload(&self, path: &str) -> Option<String> {
+        let err = self.cache.update(path).err()?;
+        if err.error.kind() == std::io::ErrorKind::NotFound {
+            info!("{}", err);
+            self.diags.remove(path);
+            return Some(path.to_owned());
+        }
+
+        self.client
+            .show_message(MessageType::WARNING, err.to_string())
+            .await;
+        None
+    }
+
+    /// caches the tokens of an open document in place of the file on disk,
+    /// other files only need checking again if its exports changed
+    fn sync_document(&self, path: &str, document: &Document) {
+        let exports = Exports::of(&document.tokens);
+        let unchanged = self
+            .cache
+            .symbols
+            .get(path)
+            .is_some_and(|tokens| Exports::of(&tokens) == exports);
+        self.cache
+            .insert(path, document.tokens.clone(), document.errors.clone());
+        if !unchanged {
+            self.drop_lint_context();
+        }
+    }
+
+    /// stores the tokens of every folder on disk for the next session
+    fn save_index(&self) {
+        for folder in self.folders.read().unwrap().iter() {
+            if let Some(file) = index_file(&folder.root) {
+                if let Err(err) = DiskCache::save(&self.cache, &file, |path| folder.indexes(path)) {
+                    info!(
+                        "failed to store index of '{}': {:?}",
+                        folder.root.display(),
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    /// settings for the folder at `uri`, asked from the client if it supports
+    /// `workspace/configuration` and taken from `initializationOptions` otherwise
+    async fn folder_config(&self, uri: &Url) -> Config {
+        let mut settings = self.settings.read().unwrap().clone();
+        if self.pull_configuration.load(Ordering::Relaxed) {
+            let item = ConfigurationItem {
+                scope_uri: Some(uri.clone()),
+                section: Some("srls".to_string()),
+            };
+            match self.client.configuration(vec![item]).await {
+                Ok(values) => {
+                    if let Some(value) = values.into_iter().next().filter(|v| !v.is_null()) {
+                        settings = Some(value);
+                    }
+                }
+                Err(err) => info!("failed to fetch configuration for {}: {:?}", uri, err),
+            }
+        }
+
+        let config = Config::from_value(settings).resolved(&uri_to_path(uri).unwrap_or_default());
+        for code in config.rules.keys().filter(|code| !is_rule(code)) {
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("srls: unknown rule `{code}` in the `rules` setting"),
+                )
+                .await;
+        }
+        config
+    }
+
+    /// asks for the settings of every folder again
+    async fn reload_configuration(&self) {
+        let folders = self.folders.read().unwrap().clone();
+        for folder in folders {
+            let config = self.folder_config(&folder.uri).await;
+            if config == folder.config {
+                continue;
+            }
+            info!(
+                "configuration of '{}' changed to {:?}",
+                folder.root.display(),
+                config
+            );
+
+            if let Some(updated) = self
+                .folders
+                .write()
+                .unwrap()
+                .iter_mut()
+                .find(|f| f.uri == folder.uri)
+            {
+                updated.set_config(config);
+            }
+        }
+    }
+
+    /// the declaration of the `kind` named like `used`, preferring the local one in scope,
+    /// then one in the same file at `path` and then any indexed one
+    fn declaration_of(
+        &self,
+        path: &str,
+        tokens: &[Token],
+        used: &Token,
+        kind: TokenKind,
+    ) -> Option<(Token, String)> {
+        let named = |tok: &&Token| tok.kind == kind && tok.name == used.name;
+        tokens
+            .iter()
+            .filter(named)
+            .rfind(|tok| {
+                matches!(tok.scope, TokenScope::Local(_)) && tok.in_scope(used.place.start)
+            })
+            .or_else(|| tokens.iter().find(named))
+            .map(|tok| (tok.clone(), path.to_owned()))
+            .or_else(|| {
+                let decl = self
+                    .cache
+                    .index
+                    .lookup(&used.name)
+                    .into_iter()
+                    .find(|decl| decl.token.kind == kind)?;
+                Some((decl.token, decl.path))
+            })
+    }
+
+    /// hover text of the procedure, variable or parameter at `at` in the file at `path`
+    fn token_hover(&self, path: &str, tokens: &[Token], at: Position) -> Option<String> {
+        let found = tokens
+            .iter()
+            .filter(|tok| {
+                matches!(
+                    tok.kind,
+                    TokenKind::VariableUse
+                        | TokenKind::FunctionCall
+                        | TokenKind::VariableAssignment
+                        | TokenKind::Function
+                )
+            })
+            .rfind(|tok| match tok.kind {
+                // an assignment spans its value
+                TokenKind::VariableAssignment => pos_in_range(&at, &name_range(tok)),
+                _ => pos_in_range(&at, &tok.place),
+            })?;
+        info!("{:?} at {:?}", found, at);
+
+        match found.kind {
+            TokenKind::FunctionCall => {
+                if let Some(keyword) = keyword(&found.name) {
+                    return Some(keyword.markdown());
+                }
+                match self.declaration_of(path, tokens, found, TokenKind::Function) {
+                    Some((declaration, declared_in)) => Some(declaration_hover(
+                        tokens,
+                        &declaration,
+                        &declared_in,
+                        declaration.value_type,
+                    )),
+                    None => builtin(&found.name).map(Builtin::markdown),
+                }
+            }
+            TokenKind::VariableUse => {
+                let (declaration, declared_in) =
+                    self.declaration_of(path, tokens, found, TokenKind::VariableAssignment)?;
+                let value_type =
+                    variable_type(&assignments(tokens), found).or(declaration.value_type);
+                Some(declaration_hover(
+                    tokens,
+                    &declaration,
+                    &declared_in,
+                    value_type,
+                ))
+            }
+            _ => Some(declaration_hover(tokens, found, path, found.value_type)),
+        }
+    }
+
+    /// edits renaming the global variable or procedure `name` outside of library roots
+    fn rename_global(
+        &self,
+        folders: &[Folder],
+        name: &str,
+        renamed: &str,
+    ) -> HashMap<Url, Vec<TextEdit>> {
+        let mut changes = HashMap::new();
+        for entry in self.cache.symbols.iter() {
+            let path = entry.key();
+            if folders.iter().any(|folder| folder.config.is_library(path)) {
+                continue;
+            }
+            let edits: Vec<TextEdit> = global_references(entry.value(), name)
+                .into_iter()
+                .map(|range| TextEdit::new(range, renamed.to_string()))
+                .collect();
+            if let (false, Some(uri)) = (edits.is_empty(), key_to_uri(path)) {
+                changes.insert(uri, edits);
+            }
+        }
+        changes
+    }
+
+    /// drops every cached file no folder or library root indexes anymore and is not open,
+    /// returns the dropped paths
+    fn prune(&self) -> Vec<String> {
+        let folders = self.folders.read().unwrap().clone();
+        let stale: Vec<String> = self
+            .cache
+            .symbols
+            .iter()
+            .map(|entry| entry.key().to_owned())
+            .filter(|path| {
+                !self.documents.contains_key(path)
+                    && !folders.iter().any(|folder| folder.indexes(path))
+            })
+            .collect();
+
+        for path in stale.iter() {
+            self.cache.remove(path);
+            self.diags.remove(path);
+        }
+        stale
+    }
+
+    /// the context of the last check, built again if the exports of a file changed since
+    fn lint_context(&self, folders: &[Folder]) -> Arc<LintContext> {
+        if let Some(context) = self.lint.read().unwrap().as_ref() {
+            return context.clone();
+        }
+        let changes = self.lint_changes.load(Ordering::SeqCst);
+        let context = Arc::new(LintContext::new(&self.cache, folders));
+        let mut lint = self.lint.write().unwrap();
+        if self.lint_changes.load(Ordering::SeqCst) == changes {
+            *lint = Some(context.clone());
+        }
+        context
+    }
+
+    fn drop_lint_context(&self) {
+        let mut lint = self.lint.write().unwrap();
+        *lint = None;
+        self.lint_changes.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// recomputes the diagnostics of `paths`, every cached file if there are none,
+    /// returns the files whose diagnostics changed
+    fn refresh_diagnostics(&self, paths: Option<Vec<String>>) -> Vec<(String, Vec<Diagnostic>)> {
+        let folders = self.folders.read().unwrap().clone();
+        let context = self.lint_context(&folders);
+        let is_global = |name: &str| context.globals.contains(name);
+        let signature_of = |name: &str| context.signature_of(name);
+        let settings = Config::from_value(self.settings.read().unwrap().clone());
+        let paths = paths.unwrap_or_else(|| {
+            self.cache
+                .symbols
+                .iter()
+                .map(|entry| entry.key().to_owned())
+                .collect()
+        });
+        let mut changed = vec![];
+
+        for path in paths {
+            let Some(tokens) = self.cache.symbols.get(&path).map(|toks| toks.clone()) else {
+                continue;
+            };
+            if folders.iter().any(|folder| folder.config.is_library(&path)) {
+                continue;
+            }
+            let mut diagnostics = self
+                .cache
+                .errors
+                .get(&path)
+                .map(|errs| errs.clone())
+                .unwrap_or_default();
+            diagnostics.append(&mut context.graph.undefined_variables(&path, &tokens));
+            diagnostics.append(&mut unused_variables(&tokens));
+            diagnostics.append(&mut shadowed_globals(&tokens, is_global));
+            diagnostics.append(&mut implicit_globals(&tokens, is_global));
+            let config = owning_folder(&folders, &path).map_or(&settings, |folder| &folder.config);
+            diagnostics.append(&mut wrong_arguments(&tokens, signature_of));
+            diagnostics.append(&mut format_mismatches(&tokens));
+            diagnostics.append(&mut type_mismatches(&tokens, signature_of));
+            diagnostics.append(&mut unprefixed_globals(&tokens, &config.prefixes));
+            diagnostics.append(&mut deprecated_calls(&tokens, |name| {
+                config.deprecation(name)
+            }));
+            let diagnostics = configure(suppress(diagnostics, &tokens), &config.rules);
+
+            if self.diags.get(&path).is_none_or(|old| *old != diagnostics) {
+                self.diags.insert(path.clone(), diagnostics.clone());
+                changed.push((path, diagnostics));
+            }
+        }
+
+        changed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        changed
+    }
+
+    /// clears the diagnostics of `removed` files and publishes all that changed,
+    /// while indexing runs those are held back as most globals are still unknown
+    async fn publish_diagnostics(&self, removed: Vec<String>) {
+        self.drop_lint_context();
+        for path in removed {
+            update_diagnostics(&self.client, &path, vec![]).await;
+        }
+        if self.indexed.load(Ordering::SeqCst) != self.indexing.load(Ordering::SeqCst) {
+            return;
+        }
+        for (path, diags) in self.refresh_diagnostics(None) {
+            update_diagnostics(&self.client, &path, diags).await;
+        }
+    }
+
+    /// publishes the diagnostics of `path` once edits pause, those of every file
+    /// if the exports of an edited file changed in the meantime
+    fn schedule_diagnostics(&self, path: String) {
+        self.edited.lock().unwrap().insert(path);
+        let edit = self.edits.fetch_add(1, Ordering::SeqCst) + 1;
+        let backend = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(DIAGNOSTICS_DELAY).await;
+            if backend.edits.load(Ordering::SeqCst) != edit {
+                return;
+            }
+            let edited: Vec<String> = backend.edited.lock().unwrap().drain().collect();
+            // indexing publishes everything once it is done
+            if backend.indexed.load(Ordering::SeqCst) != backend.indexing.load(Ordering::SeqCst) {
+                return;
+            }
+            let everything = backend.lint.read().unwrap().is_none();
+            let paths = (!everything).then_some(edited);
+            for (path, diags) in backend.refresh_diagnostics(paths) {
+                update_diagnostics(&backend.client, &path, diags).await;
+            }
+        });
+    }
+}
+
+#[tower_lsp::async_trait]
+impl LanguageServer for Backend {
+    async fn initialize(&self, init_params: InitializeParams) -> Result<InitializeResult> {
+        let mut uris: Vec<Url> = init_params
+            .workspace_folders
+            .unwrap_or_default()
+            .into_iter()
+            .map(|folder| folder.uri)
+            .collect();
+        if uris.is_empty() {
+            uris.push(
+                init_params
+                    .root_uri
+                    .ok_or(Error::new(ErrorCode::InvalidParams))?,
+            );
+        }
+        info!(target: "Backend", "Initializing Language Server");
+
+        self.pull_configuration.store(
+            init_params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.configuration)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+        self.watch_files.store(
+            init_params
+                .capabilities
+                .workspace
+                .as_ref()
+                .and_then(|workspace| workspace.did_change_watched_files)
+                .and_then(|watched| watched.dynamic_registration)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+        self.report_progress.store(
+            init_params
+                .capabilities
+                .window
+                .as_ref()
+                .and_then(|window| window.work_done_progress)
+                .unwrap_or(false),
+            Ordering::Relaxed,
+        );
+        *self.settings.write().unwrap() = init_params.initialization_options;
+        set_encoding(Encoding::negotiate(
+            init_params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        ));
+
+        for uri in uris {
+            let Some(root) = uri_to_path(&uri) else {
+                info!("skipping folder {} outside of the file system", uri);
+                continue;
+            };
+            let config = Config::from_value(self.settings.read().unwrap().clone()).resolved(&root);
+            self.folders
+                .write()
+                .unwrap()
+                .push(Folder::new(uri, root, config));
+        }
 
         Ok(InitializeResult {
             server_info: None,
             capabilities: ServerCapabilities {
+                position_encoding: Some(encoding().kind()),
                 workspace: Some(WorkspaceServerCapabilities {
                     workspace_folders: Some(WorkspaceFoldersServerCapabilities {
                         supported: Some(true),
@@ -124,21 +865,97 @@ impl LanguageServer for Backend {
                     ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["(".to_string(), " ".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
+                )),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![CodeActionKind::QUICKFIX]),
+                        work_done_progress_options: Default::default(),
+                        resolve_provider: None,
+                    },
+                )),
                 ..ServerCapabilities::default()
             },
         })
     }
 
     async fn initialized(&self, _: InitializedParams) {
-        for (path, diags) in self.diags.clone().into_iter() {
-            update_diagnostics(&self.client, &path, diags).await;
+        if self.pull_configuration.load(Ordering::Relaxed) {
+            self.reload_configuration().await;
+        }
+
+        if self.watch_files.load(Ordering::Relaxed) {
+            let options = DidChangeWatchedFilesRegistrationOptions {
+                watchers: skill_file_globs()
+                    .into_iter()
+                    .map(|glob| FileSystemWatcher {
+                        glob_pattern: GlobPattern::String(glob),
+                        kind: None,
+                    })
+                    .collect(),
+            };
+            let registration = Registration {
+                id: "srls-watched-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(options).ok(),
+            };
+            if let Err(err) = self.client.register_capability(vec![registration]).await {
+                info!("failed to register file watchers: {:?}", err);
+            }
         }
+
+        self.spawn_indexing();
     }
 
     async fn shutdown(&self) -> Result<()> {
+        self.indexing.fetch_add(1, Ordering::SeqCst);
+        self.save_index();
         Ok(())
     }
 
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        if !self.pull_configuration.load(Ordering::Relaxed) {
+            *self.settings.write().unwrap() = Some(params.settings);
+        }
+
+        self.reload_configuration().await;
+        self.spawn_indexing();
+    }
+
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        self.folders
+            .write()
+            .unwrap()
+            .retain(|folder| !params.event.removed.iter().any(|f| f.uri == folder.uri));
+
+        for added in params.event.added {
+            let Some(root) = uri_to_path(&added.uri) else {
+                info!("skipping folder {} outside of the file system", added.uri);
+                continue;
+            };
+            let config = self.folder_config(&added.uri).await;
+            info!(target: "Backend", "Adding folder '{}'", root.display());
+            self.folders
+                .write()
+                .unwrap()
+                .push(Folder::new(added.uri, root, config));
+        }
+
+        self.spawn_indexing();
+    }
+
     async fn execute_command(&self, params: ExecuteCommandParams) -> Result<Option<Value>> {
         if params.command == "custom.notification" {
             self.client
@@ -158,112 +975,301 @@ impl LanguageServer for Backend {
         }
     }
 
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let path = uri_to_key(&params.text_document.uri);
+        let folders = self.folders.read().unwrap().clone();
+        let prefixes = match owning_folder(&folders, &path) {
+            Some(folder) => folder.config.prefixes.clone(),
+            None => Config::from_value(self.settings.read().unwrap().clone()).prefixes,
+        };
+        let mut actions = vec![];
+
+        for diagnostic in params.context.diagnostics.iter() {
+            let (Some(NumberOrString::String(code)), Some(Value::String(data))) =
+                (&diagnostic.code, &diagnostic.data)
+            else {
+                continue;
+            };
+
+            if code == GLOBAL_PREFIX.code {
+                for (at, prefix) in prefixes.iter().enumerate() {
+                    let renamed = format!("{prefix}{data}");
+                    let changes = self.rename_global(&folders, data, &renamed);
+                    actions.push(quick_fix(
+                        format!("Rename `{data}` to `{renamed}`"),
+                        diagnostic,
+                        changes,
+                        at == 0,
+                    ));
+                }
+            } else if code == DEPRECATED_FUNCTION.code {
+                let edit = TextEdit::new(diagnostic.range, data.clone());
+                let changes = HashMap::from([(params.text_document.uri.clone(), vec![edit])]);
+                actions.push(quick_fix(
+                    format!("Replace with `{data}`"),
+                    diagnostic,
+                    changes,
+                    true,
+                ));
+            }
+        }
+
+        Ok(Some(actions))
+    }
+
     async fn completion(&self, cparams: CompletionParams) -> Result<Option<CompletionResponse>> {
-        info!("triggered completion");
-        let path = cparams
-            .text_document_position
-            .text_document
-            .uri
-            .path()
-            .to_string();
-        info!("for: {:?}", path);
-        info!("with: {:?}", self.cache.symbols);
-        let resp = self.cache.symbols.get(&path).unwrap();
-        info!("returned: {:?}", resp);
-        // Ok(Some(CompletionResponse::Array(
-        //     resp.iter()
-        //         .filter_map(|(range, completion)| match range {
-        //             Some(range) => {
-        //                 let trigger_pos = cparams.text_document_position.position;
-        //                 let mut ret: Option<CompletionItem> = None;
-        //                 if trigger_pos.line > range.start.line && trigger_pos.line < range.end.line
-        //                 {
-        //                     ret = Some(completion.to_owned())
-        //                 } else {
-        //                     if trigger_pos.line == range.start.line {
-        //                         if trigger_pos.character > range.start.character {
-        //                             ret = Some(completion.to_owned())
-        //                         }
-        //                     } else if trigger_pos.line == range.end.line {
-        //                         if trigger_pos.character < range.end.character {
-        //                             ret = Some(completion.to_owned())
-        //                         }
-        //                     }
-        //                 };
-        //                 ret
-        //             }
-        //             None => Some(completion.to_owned()),
-        //         })
-        //         .collect(),
-        // )))
-        Ok(Some(CompletionResponse::Array(vec![])))
+        let position = cparams.text_document_position.position;
+        let path = uri_to_key(&cparams.text_document_position.text_document.uri);
+        let prefix = match self.documents.get(&path) {
+            Some(document) => identifier_before(&document.text, position),
+            None => std::fs::read_to_string(&path)
+                .map(|text| identifier_before(&text, position))
+                .unwrap_or_default(),
+        };
+        info!("completing {:?} in {:?}", prefix, path);
+
+        let mut items: Vec<CompletionItem> = self
+            .cache
+            .symbols
+            .get(&path)
+            .map(|toks| {
+                toks.iter()
+                    .filter(|tok| {
+                        matches!(tok.scope, TokenScope::Local(_))
+                            && matches!(
+                                tok.kind,
+                                TokenKind::VariableAssignment | TokenKind::Function
+                            )
+                            && tok.name.starts_with(&prefix)
+                    })
+                    .filter_map(|tok| tok.to_completion_item(Some(position)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let globals = self.cache.index.prefixed(&prefix, COMPLETION_LIMIT + 1);
+        let is_incomplete = globals.len() > COMPLETION_LIMIT;
+        for decl in globals.into_iter().take(COMPLETION_LIMIT) {
+            if !items.iter().any(|item| item.label == decl.token.name) {
+                items.extend(decl.token.to_completion_item(None));
+            }
+        }
+
+        Ok(Some(CompletionResponse::List(CompletionList {
+            is_incomplete,
+            items,
+        })))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let symbols = self
+            .cache
+            .index
+            .fuzzy(&params.query, COMPLETION_LIMIT)
+            .into_iter()
+            .filter_map(|decl| {
+                key_to_uri(&decl.path).map(|uri| decl.token.to_symbol_information(uri))
+            })
+            .collect();
+        Ok(Some(symbols))
     }
 
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
         info!("doc/hover: {:?}", params.clone());
-        let document_hover_pos = &params.text_document_position_params.position;
-        let document_tokens = self.cache.symbols.get(
-            params
-                .text_document_position_params
-                .text_document
-                .uri
-                .path(),
-        );
-        let matched = document_tokens.map_or(None, |toks| {
-            let found = toks
-                .iter()
-                .filter(|tok| match tok.kind {
-                    TokenKind::VariableUse => true,
-                    _ => false,
-                })
-                .filter(|tok| pos_in_range(document_hover_pos, &tok.place))
-                .rev()
-                .next()
-                .map(|tok| tok.clone());
-
-            info!("{:?} at {:?}", found.clone(), document_hover_pos);
-
-            match found {
-                Some(found_token) => toks
-                    .iter()
-                    .filter(|tok| match tok.kind {
-                        TokenKind::VariableAssignment => found_token.name == tok.name,
-                        _ => false,
-                    })
-                    .next()
-                    .map(|tok| tok.clone()),
-                None => None,
-            }
+        let position = params.text_document_position_params.position;
+        let path = uri_to_key(&params.text_document_position_params.text_document.uri);
+        let tokens = self
+            .cache
+            .symbols
+            .get(&path)
+            .map(|toks| toks.clone())
+            .unwrap_or_default();
+        let markdown = self.token_hover(&path, &tokens, position).or_else(|| {
+            let text = match self.documents.get(&path) {
+                Some(document) => document.text.clone(),
+                None => std::fs::read_to_string(&path).unwrap_or_default(),
+            };
+            text_hover(&text, position)
         });
 
-        let ret = Ok(matched.map_or(None, |tok| {
-            let name = tok.name;
-            let scope = tok.scope.value();
-            let line = tok.place.start.line;
-            let doc = tok.documentation;
-            tok.info.map(|info| Hover {
-                contents: HoverContents::Scalar(MarkedString::String(format!(
-                    "*{scope}* **{name}**\n\n*declared on line {line}*:\n```lisp\n  {info}\n```\n---\n{doc}",
-                    scope = scope,
-                    name = name,
-                    line = line,
-                    info = info,
-                    doc = doc.unwrap_or("".to_string())
-                ))),
-                range: None,
-            })
+        let ret = Ok(markdown.map(|value| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
         }));
 
         info!("{:?}", ret.clone());
         ret
     }
 
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let position = params.text_document_position_params.position;
+        let path = uri_to_key(&params.text_document_position_params.text_document.uri);
+        let text = match self.documents.get(&path) {
+            Some(document) => document.text.clone(),
+            None => std::fs::read_to_string(&path).unwrap_or_default(),
+        };
+        let offset = LineIndex::new(&text, encoding()).offset(position);
+        let Some((name, argument)) = call_at(&text[..offset]) else {
+            return Ok(None);
+        };
+        info!("signature of {:?} at {:?}", name, argument);
+
+        let declaration = self
+            .cache
+            .symbols
+            .get(&path)
+            .and_then(|toks| {
+                toks.iter()
+                    .find(|tok| tok.kind == TokenKind::Function && tok.name == name)
+                    .cloned()
+            })
+            .or_else(|| self.cache.find_global(&name))
+            .filter(|tok| tok.kind == TokenKind::Function);
+        let (signature, doc) = match declaration {
+            Some(tok) => (
+                procedure_signature(&tok),
+                tok.documentation.as_deref().map(Docstring::parse),
+            ),
+            None => match builtin(&name) {
+                Some(builtin) => (
+                    Some(builtin.signature.clone()),
+                    Some(Docstring {
+                        summary: builtin.description.clone(),
+                        ..Default::default()
+                    }),
+                ),
+                None => (None, None),
+            },
+        };
+        let Some(signature) = signature else {
+            return Ok(None);
+        };
+
+        let markdown = |value: String| {
+            Documentation::MarkupContent(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            })
+        };
+        let parameters = signature
+            .parameters()
+            .into_iter()
+            .map(|param| ParameterInformation {
+                label: ParameterLabel::Simple(param.clone()),
+                documentation: doc
+                    .as_ref()
+                    .and_then(|doc| doc.param(param))
+                    .map(|tag| markdown(tag.markdown().trim_start().to_string())),
+            })
+            .collect();
+        // the parameters are listed on their own
+        let documentation = doc
+            .map(|doc| Docstring {
+                params: vec![],
+                ..doc
+            })
+            .map(|doc| doc.markdown())
+            .filter(|doc| !doc.is_empty())
+            .map(markdown);
+        // left out for arguments the procedure does not take, clients may then fall back to the first
+        let active_parameter = signature.active_parameter(&argument).map(|at| at as u32);
+
+        Ok(Some(SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label: signature.label(),
+                documentation,
+                parameters: Some(parameters),
+                active_parameter,
+            }],
+            active_signature: Some(0),
+            active_parameter: None,
+        }))
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let folders = self.folders.read().unwrap().clone();
+        let mut removed = vec![];
+
+        for change in params.changes {
+            let path = uri_to_key(&change.uri);
+            info!("{:?} on disk: {:?}", change.typ, path);
+
+            if change.typ == FileChangeType::DELETED {
+                // a deleted directory only reports itself, not the files below it
+                let deleted: Vec<String> = self
+                    .cache
+                    .symbols
+                    .iter()
+                    .map(|entry| entry.key().to_owned())
+                    .filter(|cached| Path::new(cached).starts_with(&path))
+                    .collect();
+                for cached in deleted {
+                    self.cache.remove(&cached);
+                    self.diags.remove(&cached);
+                    removed.push(cached);
+                }
+            } else if is_skill_file(&path)
+                && !self.documents.contains_key(&path)
+                && folders.iter().any(|folder| folder.indexes(&path))
+            {
+                removed.extend(self.reload(&path).await);
+            }
+        }
+
+        self.publish_diagnostics(removed).await;
+    }
+
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let path = uri_to_key(&params.text_document.uri);
+        let document = Document::new(params.text_document.text);
+        self.sync_document(&path, &document);
+        self.documents.insert(path.clone(), document);
+        self.schedule_diagnostics(path);
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let path = uri_to_key(&params.text_document.uri);
+        let Some(mut document) = self.documents.get_mut(&path) else {
+            return;
+        };
+        for change in params.content_changes {
+            document.edit(change.range, &change.text);
+        }
+        self.sync_document(&path, &document);
+        drop(document);
+        self.schedule_diagnostics(path);
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        let path = uri_to_key(&params.text_document.uri);
+        self.documents.remove(&path);
+        // unsaved changes are gone, the file on disk counts again
+        let mut removed = vec![];
+        if uri_to_path(&params.text_document.uri).is_none() {
+            // an unsaved buffer has nothing to fall back to
+            self.cache.remove(&path);
+            self.diags.remove(&path);
+            removed.push(path);
+        } else if self.cache.symbols.contains_key(&path) {
+            removed.extend(self.reload(&path).await);
+        }
+        self.publish_diagnostics(removed).await;
+    }
+
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
-        let path = params.text_document.uri.path().to_string();
+        let path = uri_to_key(&params.text_document.uri);
+        if self.documents.contains_key(&path) {
+            return;
+        }
         info!("updating cache for {:?}", path.clone());
-        let (_, parsed_errors) = self.cache.update(path.as_ref());
-        self.diags.insert(path.to_owned(), parsed_errors.clone());
-        update_diagnostics(&self.client, &path, parsed_errors).await;
+        let removed = self.reload(&path).await.into_iter().collect();
+        self.publish_diagnostics(removed).await;
     }
 }
 
@@ -277,8 +1283,20 @@ async fn main() {
 
     let (service, socket) = LspService::new(|client| Backend {
         client,
-        cache: TokenCache::new(),
-        diags: DashMap::new(),
+        cache: Arc::new(TokenCache::new()),
+        diags: Arc::new(DashMap::new()),
+        documents: Arc::new(DashMap::new()),
+        folders: Arc::new(RwLock::new(vec![])),
+        settings: Arc::new(RwLock::new(None)),
+        pull_configuration: Arc::new(AtomicBool::new(false)),
+        watch_files: Arc::new(AtomicBool::new(false)),
+        report_progress: Arc::new(AtomicBool::new(false)),
+        indexing: Arc::new(AtomicUsize::new(0)),
+        indexed: Arc::new(AtomicUsize::new(0)),
+        lint: Arc::new(RwLock::new(None)),
+        lint_changes: Arc::new(AtomicUsize::new(0)),
+        edited: Arc::new(Mutex::new(HashSet::new())),
+        edits: Arc::new(AtomicUsize::new(0)),
     });
     info!("Creating server instance.");
     Server::new(stdin, stdout, socket).serve(service).await;
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, metadata, read, rename, write};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Diagnostic;
+
+use crate::cache::{content_hash, read_skill_file, FileStamp, TokenCache};
+use crate::encoding::encoding;
+use crate::token::Token;
+
+/// bumped whenever the stored layout changes, together with the
+/// package version it decides whether a stored index can be used
+const FORMAT_VERSION: u32 = 9;
+
+/// positions are stored in the negotiated encoding, another client cannot use them
+fn version() -> String {
+    format!(
+        "{}-{}-{:?}",
+        FORMAT_VERSION,
+        env!("CARGO_PKG_VERSION"),
+        encoding()
+    )
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredFile {
+    stamp: FileStamp,
+    tokens: Vec<Token>,
+    errors: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredIndex {
+    version: String,
+    files: HashMap<String, StoredFile>,
+}
+
+/// where the index of the folder at `root` is kept, below `$XDG_CACHE_HOME/srls`
+pub fn index_file(root: &Path) -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    let key = content_hash(root.to_str()?);
+
+    Some(cache_home.join("srls").join(format!("{key:016x}.json")))
+}
+
+/// tokens of a previous session, read back from disk
+#[derive(Debug, Default)]
+pub struct DiskCache {
+    files: HashMap<String, StoredFile>,
+}
+
+impl DiskCache {
+    /// reads a stored index, unreadable, corrupt and outdated ones are discarded
+    pub fn load(file: &Path) -> DiskCache {
+        let stored = read(file)
+            .map_err(|err| err.to_string())
+            .and_then(|content| {
+                serde_json::from_slice::<StoredIndex>(&content).map_err(|err| err.to_string())
+            });
+
+        match stored {
+            Ok(index) if index.version == version() => {
+                info!("restored {} files from {:?}", index.files.len(), file);
+                DiskCache { files: index.files }
+            }
+            Ok(index) => {
+                info!("discarding {:?} of version {}", file, index.version);
+                DiskCache::default()
+            }
+            Err(err) => {
+                info!("discarding {:?}: {}", file, err);
+                DiskCache::default()
+            }
+        }
+    }
+
+    pub fn extend(&mut self, other: DiskCache) {
+        self.files.extend(other.files);
+    }
+
+    /// moves the stored tokens of `path` into `cache`, unless the file changed since,
+    /// a touched file with the same content is still taken from the store
+    pub fn restore(&self, cache: &TokenCache, path: &str) -> bool {
+        let Some(stored) = self.files.get(path) else {
+            return false;
+        };
+        let modified = metadata(path).and_then(|meta| meta.modified()).ok();
+        let unchanged = (modified.is_some() && modified == stored.stamp.modified)
+            || read_skill_file(path)
+                .is_ok_and(|(content, _)| content_hash(&content) == stored.stamp.hash);
+        if !unchanged {
+            return false;
+        }
+
+        cache.insert(path, stored.tokens.clone(), stored.errors.clone());
+        cache.stamps.insert(
+            path.to_owned(),
+            FileStamp {
+                modified,
+                hash: stored.stamp.hash,
+            },
+        );
+        true
+    }
+
+    /// writes every cached file `select` picks to `file`, replacing it at once
+    /// so a crash cannot leave a half written index behind
+    pub fn save(cache: &TokenCache, file: &Path, select: impl Fn(&str) -> bool) -> io::Result<()> {
+        let files = cache
+            .stamps
+            .iter()
+            .filter(|stamp| select(stamp.key()))
+            .filter_map(|stamp| {
+                let path = stamp.key();
+                Some((
+                    path.to_owned(),
+                    StoredFile {
+                        stamp: stamp.value().clone(),
+                        tokens: cache.symbols.get(path)?.clone(),
+                        errors: cache
+                            .errors
+                            .get(path)
+                            .map(|errs| errs.clone())
+                            .unwrap_or_default(),
+                    },
+                ))
+            })
+            .collect();
+        let index = StoredIndex {
+            version: version(),
+            files,
+        };
+
+        if let Some(dir) = file.parent() {
+            create_dir_all(dir)?;
+        }
+        let partial = file.with_extension("json.partial");
+        write(&partial, serde_json::to_vec(&index)?)?;
+        rename(partial, file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write, File};
+    use std::time::{Duration, SystemTime};
+
+    use crate::cache::TokenCache;
+
+    use super::DiskCache;
+
+    #[test]
+    fn restore_unchanged_files() {
+        let root = std::env::temp_dir().join(format!("srls-persist-{}", std::process::id()));
+        create_dir_all(&root).unwrap();
+        let index = root.join("index.json");
+        let (kept, edited) = (root.join("kept.il"), root.join("edited.il"));
+        write(&kept, "a = 1\n").unwrap();
+        write(&edited, "b = 2\n").unwrap();
+        let (kept, edited) = (kept.to_str().unwrap(), edited.to_str().unwrap());
+
+        let cache = TokenCache::new();
+        cache.update(kept).unwrap();
+        cache.update(edited).unwrap();
+        DiskCache::save(&cache, &index, |_| true).unwrap();
+
+        // same length as before, with a new mtime the content hash has to decide
+        write(edited, "b = 3\n").unwrap();
+        File::options()
+            .write(true)
+            .open(edited)
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(5))
+            .unwrap();
+        let stored = DiskCache::load(&index);
+        let restored = TokenCache::new();
+        assert!(stored.restore(&restored, kept));
+        assert!(!stored.restore(&restored, edited));
+        assert_eq!(restored.symbols.get(kept).unwrap()[0].name, "a");
+        assert!(restored.symbols.get(edited).is_none());
+    }
+
+    #[test]
+    fn discard_broken_index() {
+        let root = std::env::temp_dir().join(format!("srls-persist-broken-{}", std::process::id()));
+        create_dir_all(&root).unwrap();
+        let (corrupt, outdated) = (root.join("corrupt.json"), root.join("outdated.json"));
+        write(&corrupt, "{\"version\": \"1-0.3.0\", \"files\": {").unwrap();
+        write(&outdated, "{\"version\": \"0-0.0.0\", \"files\": {}}").unwrap();
+
+        assert!(DiskCache::load(&corrupt).files.is_empty());
+        assert!(DiskCache::load(&outdated).files.is_empty());
+        assert!(DiskCache::load(&root.join("missing.json")).files.is_empty());
+    }
+}
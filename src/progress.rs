@@ -0,0 +1,78 @@
+use log::info;
+use tower_lsp::lsp_types::notification::Progress as ProgressNotification;
+use tower_lsp::lsp_types::request::WorkDoneProgressCreate;
+use tower_lsp::lsp_types::{
+    NumberOrString, ProgressParams, ProgressParamsValue, WorkDoneProgress, WorkDoneProgressBegin,
+    WorkDoneProgressCreateParams, WorkDoneProgressEnd, WorkDoneProgressReport,
+};
+use tower_lsp::Client;
+
+/// a `$/progress` work done report shown by the client
+pub struct Progress {
+    client: Client,
+    token: NumberOrString,
+    percentage: u32,
+}
+
+impl Progress {
+    /// asks the client to create a progress report, `None` if it refuses
+    pub async fn begin(client: &Client, token: &str, title: &str) -> Option<Progress> {
+        let token = NumberOrString::String(token.to_string());
+        let created = client
+            .send_request::<WorkDoneProgressCreate>(WorkDoneProgressCreateParams {
+                token: token.clone(),
+            })
+            .await;
+        if let Err(err) = created {
+            info!("failed to create progress {:?}: {:?}", token, err);
+            return None;
+        }
+
+        let progress = Progress {
+            client: client.clone(),
+            token,
+            percentage: 0,
+        };
+        progress
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                cancellable: Some(false),
+                percentage: Some(0),
+                ..Default::default()
+            }))
+            .await;
+        Some(progress)
+    }
+
+    async fn send(&self, value: WorkDoneProgress) {
+        self.client
+            .send_notification::<ProgressNotification>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(value),
+            })
+            .await;
+    }
+
+    /// reports `done` out of `total`, only sent once the percentage changes
+    pub async fn report(&mut self, done: usize, total: usize) {
+        let percentage = (done * 100 / total.max(1)) as u32;
+        if percentage == self.percentage {
+            return;
+        }
+        self.percentage = percentage;
+
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(format!("{done}/{total} files")),
+            percentage: Some(percentage),
+            ..Default::default()
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: impl Into<String>) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message.into()),
+        }))
+        .await;
+    }
+}
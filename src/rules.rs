@@ -0,0 +1,545 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use tower_lsp::lsp_types::{
+    CodeDescription, Diagnostic, DiagnosticSeverity, DiagnosticTag, NumberOrString, Range, Url,
+};
+
+use crate::token::{Token, TokenKind};
+
+/// where the rules are described, `#code` points at a single rule
+const RULE_DOCS: &str = "https://srls.invalid/rules";
+
+/// a check with a stable code to configure and suppress it by
+#[derive(Debug, PartialEq)]
+pub struct LintRule {
+    pub code: &'static str,
+    /// the SKILL Lint messages the rule stands for, accepted wherever the code is
+    /// so a checking policy carries over, empty for checks SKILL Lint does not have
+    pub skill_lint: &'static [&'static str],
+    pub severity: DiagnosticSeverity,
+    /// shown when hovering the code in a suppression comment
+    pub description: &'static str,
+}
+
+pub const SYNTAX_ERROR: LintRule = LintRule {
+    code: "syntax-error",
+    skill_lint: &["SYNTAX"],
+    severity: DiagnosticSeverity::ERROR,
+    description: "the file cannot be parsed",
+};
+
+pub const UNREADABLE_FILE: LintRule = LintRule {
+    code: "unreadable-file",
+    skill_lint: &[],
+    severity: DiagnosticSeverity::ERROR,
+    description: "the file cannot be read",
+};
+
+pub const FILE_ENCODING: LintRule = LintRule {
+    code: "file-encoding",
+    skill_lint: &[],
+    severity: DiagnosticSeverity::WARNING,
+    description: "the file is not valid UTF-8 and was read as Latin-1",
+};
+
+pub const UNDEFINED_VARIABLE: LintRule = LintRule {
+    code: "undefined-variable",
+    skill_lint: &["VAR7"],
+    severity: DiagnosticSeverity::ERROR,
+    description: "a variable is read before anything along the load chain assigns it",
+};
+
+pub const UNUSED_VARIABLE: LintRule = LintRule {
+    code: "unused-variable",
+    skill_lint: &["VAR8"],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a local variable, loop variable or parameter is never read",
+};
+
+pub const SHADOWED_GLOBAL: LintRule = LintRule {
+    code: "shadowed-global",
+    skill_lint: &["VAR13"],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a local binding hides a global variable from every procedure it calls",
+};
+
+pub const IMPLICIT_GLOBAL: LintRule = LintRule {
+    code: "implicit-global",
+    skill_lint: &["VAR12"],
+    severity: DiagnosticSeverity::WARNING,
+    description: "an assignment in a procedure creates a global variable",
+};
+
+pub const ARGUMENT_COUNT: LintRule = LintRule {
+    code: "argument-count",
+    skill_lint: &["CHK1"],
+    severity: DiagnosticSeverity::ERROR,
+    description: "a call passes too few or too many positional arguments",
+};
+
+pub const UNKNOWN_KEYWORD: LintRule = LintRule {
+    code: "unknown-keyword",
+    skill_lint: &["CHK2"],
+    severity: DiagnosticSeverity::ERROR,
+    description: "a call passes a keyword argument the procedure does not take",
+};
+
+pub const UNCHECKED_KEYWORD: LintRule = LintRule {
+    code: "unchecked-keyword",
+    skill_lint: &[],
+    severity: DiagnosticSeverity::INFORMATION,
+    description: "a keyword argument ends up in `@rest` and cannot be checked",
+};
+
+pub const FORMAT_STRING: LintRule = LintRule {
+    code: "format-string",
+    skill_lint: &["CHK11"],
+    severity: DiagnosticSeverity::ERROR,
+    description: "a format string with unknown directives or not as many directives as arguments",
+};
+
+pub const TYPE_MISMATCH: LintRule = LintRule {
+    code: "type-mismatch",
+    skill_lint: &[],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a number is passed where a string is expected or the other way around",
+};
+
+pub const GLOBAL_PREFIX: LintRule = LintRule {
+    code: "global-prefix",
+    skill_lint: &["PREFIX"],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a global variable or procedure does not start with an allowed prefix",
+};
+
+pub const DEPRECATED_FUNCTION: LintRule = LintRule {
+    code: "deprecated-function",
+    skill_lint: &["DEPRECATED"],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a call to a deprecated or removed function",
+};
+
+pub const UNUSED_SUPPRESSION: LintRule = LintRule {
+    code: "unused-suppression",
+    skill_lint: &[],
+    severity: DiagnosticSeverity::WARNING,
+    description: "a suppression comment silences nothing",
+};
+
+pub const RULES: [&LintRule; 15] = [
+    &SYNTAX_ERROR,
+    &UNREADABLE_FILE,
+    &FILE_ENCODING,
+    &UNDEFINED_VARIABLE,
+    &UNUSED_VARIABLE,
+    &SHADOWED_GLOBAL,
+    &IMPLICIT_GLOBAL,
+    &ARGUMENT_COUNT,
+    &UNKNOWN_KEYWORD,
+    &UNCHECKED_KEYWORD,
+    &FORMAT_STRING,
+    &TYPE_MISMATCH,
+    &GLOBAL_PREFIX,
+    &DEPRECATED_FUNCTION,
+    &UNUSED_SUPPRESSION,
+];
+
+impl LintRule {
+    /// a finding of this rule at its default severity
+    pub fn diagnostic(&self, range: Range, message: String) -> Diagnostic {
+        Diagnostic {
+            range,
+            severity: Some(self.severity),
+            code: Some(NumberOrString::String(self.code.to_string())),
+            code_description: Url::parse(&format!("{RULE_DOCS}#{}", self.code))
+                .ok()
+                .map(|href| CodeDescription { href }),
+            source: Some("srls".to_string()),
+            message,
+            ..Default::default()
+        }
+    }
+
+    pub fn markdown(&self) -> String {
+        let severity = match self.severity {
+            DiagnosticSeverity::ERROR => "error",
+            DiagnosticSeverity::WARNING => "warning",
+            DiagnosticSeverity::INFORMATION => "info",
+            _ => "hint",
+        };
+        let skill_lint = match self.skill_lint {
+            [] => String::new(),
+            messages => format!(", SKILL Lint `{}`", messages.join("`, `")),
+        };
+        format!(
+            "*rule* **{}**{skill_lint}, {severity} by default\n\n---\n{}",
+            self.code, self.description
+        )
+    }
+}
+
+/// how a rule is reported, set per rule code or SKILL Lint message in the `rules` setting
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleLevel {
+    Off,
+    Error,
+    Warning,
+    #[serde(alias = "info")]
+    Information,
+    Hint,
+}
+
+/// the rule reporting diagnostics with `code`, or the one standing for a SKILL Lint message
+pub fn rule(code: &str) -> Option<&'static LintRule> {
+    RULES
+        .iter()
+        .copied()
+        .find(|rule| rule.code == code || rule.skill_lint.contains(&code))
+}
+
+/// whether two names from comments or settings refer to the same rule
+fn same_rule(name: &str, other: &str) -> bool {
+    name == other || rule(name).is_some_and(|found| rule(other) == Some(found))
+}
+
+pub fn is_rule(code: &str) -> bool {
+    rule(code).is_some()
+}
+
+fn code_of(diagnostic: &Diagnostic) -> Option<&str> {
+    match &diagnostic.code {
+        Some(NumberOrString::String(code)) => Some(code),
+        _ => None,
+    }
+}
+
+/// a suppression comment with the lines it covers, no rules silence every rule
+struct Suppression<'a> {
+    comment: &'a Token,
+    rules: Vec<&'a str>,
+    lines: (u32, u32),
+}
+
+impl Suppression<'_> {
+    /// the listed rule silencing `diagnostic`, `None` for every rule
+    fn silences(&self, diagnostic: &Diagnostic) -> Option<Option<usize>> {
+        let line = diagnostic.range.start.line;
+        if line < self.lines.0 || self.lines.1 < line {
+            return None;
+        }
+        if self.rules.is_empty() {
+            return Some(None);
+        }
+        let code = code_of(diagnostic)?;
+        self.rules
+            .iter()
+            .position(|rule| same_rule(rule, code))
+            .map(Some)
+    }
+}
+
+fn suppressions(tokens: &[Token]) -> Vec<Suppression<'_>> {
+    let comments: Vec<(&Token, Vec<&str>)> = tokens
+        .iter()
+        .filter(|tok| tok.kind == TokenKind::Suppression)
+        .map(|tok| {
+            let rules = tok.info.as_deref().unwrap_or_default();
+            (tok, rules.split_whitespace().collect())
+        })
+        .collect();
+
+    // the end of a region opened by `comment` for `rule`, all rules if there is none
+    let region_end = |comment: &Token, rule: Option<&str>| {
+        comments
+            .iter()
+            .filter(|(other, _)| other.name == "enable" && other.place.start > comment.place.start)
+            .find(|(_, enabled)| {
+                enabled.is_empty()
+                    || rule.is_none_or(|rule| enabled.iter().any(|name| same_rule(name, rule)))
+            })
+            .map_or(u32::MAX, |(other, _)| other.place.start.line)
+    };
+
+    let mut suppressions = vec![];
+    for (comment, rules) in comments.iter() {
+        let line = comment.place.start.line;
+        match comment.name.as_str() {
+            "disable-next-line" => suppressions.push(Suppression {
+                comment,
+                rules: rules.clone(),
+                lines: (line + 1, line + 1),
+            }),
+            "disable-file" => suppressions.push(Suppression {
+                comment,
+                rules: rules.clone(),
+                lines: (0, u32::MAX),
+            }),
+            // up to the next `enable` of any rule
+            "disable" if rules.is_empty() => suppressions.push(Suppression {
+                comment,
+                rules: vec![],
+                lines: (line, region_end(comment, None)),
+            }),
+            // every rule up to its own `enable`
+            "disable" => suppressions.extend(rules.iter().map(|rule| Suppression {
+                comment,
+                rules: vec![*rule],
+                lines: (line, region_end(comment, Some(rule))),
+            })),
+            _ => {}
+        }
+    }
+    suppressions
+}
+
+/// drops the diagnostics silenced by suppression comments in `tokens`
+/// and reports the suppressions that silence nothing
+pub fn suppress(diagnostics: Vec<Diagnostic>, tokens: &[Token]) -> Vec<Diagnostic> {
+    let suppressions = suppressions(tokens);
+    if suppressions.is_empty() {
+        return diagnostics;
+    }
+    let mut used: Vec<Vec<bool>> = suppressions
+        .iter()
+        .map(|suppression| vec![false; suppression.rules.len().max(1)])
+        .collect();
+
+    let mut kept: Vec<Diagnostic> = diagnostics
+        .into_iter()
+        .filter(|diagnostic| {
+            let mut silenced = false;
+            for (suppression, used) in suppressions.iter().zip(used.iter_mut()) {
+                if let Some(rule) = suppression.silences(diagnostic) {
+                    used[rule.unwrap_or(0)] = true;
+                    silenced = true;
+                }
+            }
+            !silenced
+        })
+        .collect();
+
+    for (suppression, used) in suppressions.iter().zip(used) {
+        let place = suppression.comment.place;
+        let unused = |message: String| Diagnostic {
+            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+            ..UNUSED_SUPPRESSION.diagnostic(place, message)
+        };
+        if suppression.rules.is_empty() {
+            if !used[0] {
+                kept.push(unused("nothing is reported here to suppress".to_string()));
+            }
+            continue;
+        }
+        for (rule, used) in suppression.rules.iter().zip(used) {
+            if used {
+                continue;
+            }
+            let message = if is_rule(rule) {
+                format!("`{rule}` is not reported here, its suppression is unused")
+            } else {
+                format!("unknown rule `{rule}`")
+            };
+            kept.push(unused(message));
+        }
+    }
+
+    kept
+}
+
+/// drops the diagnostics of disabled rules and applies the configured severities,
+/// a level set for the code wins over one set for its SKILL Lint messages
+pub fn configure(
+    diagnostics: Vec<Diagnostic>,
+    levels: &HashMap<String, RuleLevel>,
+) -> Vec<Diagnostic> {
+    diagnostics
+        .into_iter()
+        .filter_map(|mut diagnostic| {
+            let level = code_of(&diagnostic).and_then(|code| {
+                levels.get(code).or_else(|| {
+                    rule(code)?
+                        .skill_lint
+                        .iter()
+                        .find_map(|message| levels.get(*message))
+                })
+            });
+            diagnostic.severity = match level {
+                None => diagnostic.severity,
+                Some(RuleLevel::Off) => return None,
+                Some(RuleLevel::Error) => Some(DiagnosticSeverity::ERROR),
+                Some(RuleLevel::Warning) => Some(DiagnosticSeverity::WARNING),
+                Some(RuleLevel::Information) => Some(DiagnosticSeverity::INFORMATION),
+                Some(RuleLevel::Hint) => Some(DiagnosticSeverity::HINT),
+            };
+            Some(diagnostic)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use tower_lsp::lsp_types::{DiagnosticSeverity, NumberOrString, Range};
+
+    use crate::lint::{shadowed_globals, unused_variables};
+    use crate::skill::parse_skill_content;
+
+    use super::{configure, rule, suppress, RuleLevel, RULES, SHADOWED_GLOBAL, UNUSED_VARIABLE};
+
+    #[test]
+    fn stable_codes() {
+        let codes: HashSet<&str> = RULES.iter().map(|rule| rule.code).collect();
+        assert_eq!(codes.len(), RULES.len());
+
+        let diagnostic = UNUSED_VARIABLE.diagnostic(Range::default(), "unused".to_string());
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("unused-variable".to_string()))
+        );
+        assert_eq!(diagnostic.source.as_deref(), Some("srls"));
+        assert!(diagnostic
+            .code_description
+            .unwrap()
+            .href
+            .as_str()
+            .ends_with("#unused-variable"));
+
+        assert_eq!(rule("unused-variable"), Some(&UNUSED_VARIABLE));
+        assert_eq!(rule("VAR8"), Some(&UNUSED_VARIABLE));
+        assert_eq!(rule("VAR0"), None);
+        let mut names = codes.clone();
+        for message in RULES.iter().flat_map(|rule| rule.skill_lint) {
+            assert!(names.insert(message), "`{message}` names two rules");
+        }
+        assert_eq!(
+            UNUSED_VARIABLE.markdown(),
+            "*rule* **unused-variable**, SKILL Lint `VAR8`, warning by default\n\n---\n\
+             a local variable, loop variable or parameter is never read"
+        );
+    }
+
+    #[test]
+    fn configured_levels() {
+        let diagnostics = vec![
+            UNUSED_VARIABLE.diagnostic(Range::default(), "unused".to_string()),
+            SHADOWED_GLOBAL.diagnostic(Range::default(), "shadows".to_string()),
+        ];
+        let levels: HashMap<String, RuleLevel> = serde_json::from_value(
+            serde_json::json!({"unused-variable": "off", "shadowed-global": "error"}),
+        )
+        .unwrap();
+
+        let configured = configure(diagnostics.clone(), &levels);
+        assert_eq!(configured.len(), 1);
+        assert_eq!(configured[0].message, "shadows");
+        assert_eq!(configured[0].severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(configure(diagnostics.clone(), &HashMap::new()), diagnostics);
+
+        // SKILL Lint messages configure their rule, the code itself takes precedence
+        let levels: HashMap<String, RuleLevel> = serde_json::from_value(
+            serde_json::json!({"VAR8": "off", "VAR13": "hint", "shadowed-global": "info"}),
+        )
+        .unwrap();
+        let configured = configure(diagnostics, &levels);
+        assert_eq!(configured.len(), 1);
+        assert_eq!(
+            configured[0].severity,
+            Some(DiagnosticSeverity::INFORMATION)
+        );
+    }
+
+    #[test]
+    fn suppression_comments() {
+        let code = "; srls-disable-file file-encoding\n\
+                    a = 1\n\
+                    ; srls-disable-next-line unused-variable, shadowed-global\n\
+                    let((b) nil)\n\
+                    ; srls-disable unused-variable\n\
+                    let((c) nil)\n\
+                    let((d) nil)\n\
+                    ; srls-enable unused-variable\n\
+                    let((e) nil)\n\
+                    ; srls-disable-next-line\n\
+                    f = 2\n\
+                    ; srls-disable-next-line no-such-rule\n\
+                    g = 3\n";
+        let (tokens, errors) = parse_skill_content(code);
+        assert!(errors.is_empty());
+        let diagnostics = unused_variables(&tokens);
+        assert_eq!(diagnostics.len(), 4);
+
+        let found: Vec<(u32, String)> = suppress(diagnostics, &tokens)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (8, "unused variable `e`".to_string()),
+                (
+                    0,
+                    "`file-encoding` is not reported here, its suppression is unused".to_string()
+                ),
+                (
+                    2,
+                    "`shadowed-global` is not reported here, its suppression is unused".to_string()
+                ),
+                (9, "nothing is reported here to suppress".to_string()),
+                (11, "unknown rule `no-such-rule`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn enable_closes_only_its_rules() {
+        let code = "; srls-disable unused-variable shadowed-global\n\
+                    x = 1\n\
+                    let((a) nil)\n\
+                    ; srls-enable unused-variable\n\
+                    let((b) nil)\n\
+                    let((x) x)\n";
+        let (tokens, errors) = parse_skill_content(code);
+        assert!(errors.is_empty());
+        let mut diagnostics = unused_variables(&tokens);
+        diagnostics.append(&mut shadowed_globals(&tokens, |name| name == "x"));
+        assert_eq!(diagnostics.len(), 3);
+
+        let found: Vec<(u32, String)> = suppress(diagnostics, &tokens)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(found, vec![(4, "unused variable `b`".to_string())]);
+    }
+
+    #[test]
+    fn skill_lint_messages_in_comments() {
+        let code = "; srls-disable VAR8\n\
+                    let((a) nil)\n\
+                    ; srls-enable unused-variable\n\
+                    let((b) nil)\n\
+                    ; srls-disable-next-line VAR8 VAR13\n\
+                    let((c) nil)\n";
+        let (tokens, errors) = parse_skill_content(code);
+        assert!(errors.is_empty());
+        let diagnostics = unused_variables(&tokens);
+        assert_eq!(diagnostics.len(), 3);
+
+        let found: Vec<(u32, String)> = suppress(diagnostics, &tokens)
+            .into_iter()
+            .map(|diag| (diag.range.start.line, diag.message))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, "unused variable `b`".to_string()),
+                (
+                    4,
+                    "`VAR13` is not reported here, its suppression is unused".to_string()
+                ),
+            ]
+        );
+    }
+}
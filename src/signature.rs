@@ -0,0 +1,428 @@
+use std::collections::HashMap;
+
+use crate::token::Argument;
+use crate::types::ValueType;
+
+/// the parameter list of a procedure, as written in its definition
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    /// `[s_id]` in front of the required parameters, only passed as a quoted symbol
+    pub leading: Option<String>,
+    pub required: Vec<String>,
+    pub optional: Vec<String>,
+    pub keys: Vec<String>,
+    /// `...` after the keys of a built-in, these are only the ones known
+    pub more_keys: bool,
+    pub rest: Option<String>,
+    /// what calls return, from the `=>` of built-ins or the `@return` of a docstring
+    pub returns: Option<ValueType>,
+    /// parameter types declared with `@param`, parameters named like `t_string` need none
+    pub types: HashMap<String, ValueType>,
+}
+
+/// splits a parameter list into its words and parenthesized groups
+fn items(params: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut depth = 0;
+    let mut start = None;
+    let mut in_string = false;
+
+    for (at, c) in params.char_indices() {
+        match c {
+            '"' => {
+                in_string = !in_string;
+                start.get_or_insert(at);
+            }
+            _ if in_string => {}
+            '(' => {
+                depth += 1;
+                start.get_or_insert(at);
+            }
+            ')' => depth -= 1,
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(from) = start.take() {
+                    items.push(&params[from..at]);
+                }
+            }
+            _ => {
+                start.get_or_insert(at);
+            }
+        }
+        if !in_string && depth == 0 && c == ')' {
+            if let Some(from) = start.take() {
+                items.push(&params[from..=at]);
+            }
+        }
+    }
+    if let Some(from) = start {
+        items.push(&params[from..]);
+    }
+    items
+}
+
+impl Signature {
+    /// reads `name(a b @optional (c 1) @key d @rest e)`, the head of a procedure definition,
+    /// built-ins also use `[a]` for a leading parameter and `...` for more keys
+    pub fn parse(text: &str) -> Option<Signature> {
+        let open = text.find('(')?;
+        let close = text.rfind(')')?;
+        let name = text[..open].trim();
+        if name.is_empty() || close < open {
+            return None;
+        }
+
+        let mut signature = Signature {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut section = "";
+        for item in items(&text[open + 1..close]) {
+            if item.starts_with('@') {
+                section = item;
+                continue;
+            }
+            if item == "..." {
+                signature.more_keys = true;
+                continue;
+            }
+            if let Some(leading) = item
+                .strip_prefix('[')
+                .and_then(|item| item.strip_suffix(']'))
+            {
+                signature.leading = Some(leading.to_string());
+                continue;
+            }
+            // `(name default)`
+            let param = item
+                .trim_start_matches('(')
+                .split_whitespace()
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches(')')
+                .to_string();
+            match section {
+                "" => signature.required.push(param),
+                "@optional" => signature.optional.push(param),
+                "@key" | "@keys" => signature.keys.push(param),
+                "@rest" => signature.rest = Some(param),
+                _ => {}
+            }
+        }
+        Some(signature)
+    }
+
+    /// whether a call passing `arguments` starts with the leading parameter
+    pub fn binds_leading(&self, arguments: &[Argument]) -> bool {
+        self.leading.is_some()
+            && matches!(
+                arguments.iter().find(|arg| arg.is_positional()),
+                Some(Argument::Typed(ValueType::Symbol, _))
+            )
+    }
+
+    /// the parameters a call passing `arguments` binds, with the leading one if it is given
+    pub fn for_call(&self, arguments: &[Argument]) -> Signature {
+        let mut signature = self.clone();
+        if self.binds_leading(arguments) {
+            signature
+                .required
+                .insert(0, signature.leading.take().unwrap_or_default());
+        }
+        signature
+    }
+
+    /// every parameter in order, `@rest` repeats for the remaining arguments
+    pub fn positional(&self) -> impl Iterator<Item = &String> {
+        self.required
+            .iter()
+            .chain(self.optional.iter())
+            .chain(std::iter::repeat(self.rest.iter()).flatten())
+    }
+
+    pub fn parameter_type(&self, parameter: &str) -> Option<ValueType> {
+        self.types
+            .get(parameter)
+            .copied()
+            .or_else(|| ValueType::of_parameter(parameter))
+    }
+
+    /// every parameter in the order of the label
+    pub fn parameters(&self) -> Vec<&String> {
+        self.required
+            .iter()
+            .chain(self.optional.iter())
+            .chain(self.keys.iter())
+            .chain(self.rest.iter())
+            .collect()
+    }
+
+    /// `name(a @optional b @key c @rest d)`
+    pub fn label(&self) -> String {
+        let mut words: Vec<String> = self.leading.iter().map(|id| format!("[{id}]")).collect();
+        words.extend(self.required.iter().cloned());
+        for (section, params) in [("@optional", &self.optional), ("@key", &self.keys)] {
+            if !params.is_empty() {
+                words.push(section.to_string());
+                words.extend(params.iter().cloned());
+            }
+        }
+        if self.more_keys {
+            words.push("...".to_string());
+        }
+        if let Some(rest) = &self.rest {
+            words.push("@rest".to_string());
+            words.push(rest.clone());
+        }
+        format!("{}({})", self.name, words.join(" "))
+    }
+
+    /// which of `parameters` the argument binds to
+    pub fn active_parameter(&self, argument: &ActiveArgument) -> Option<usize> {
+        let positional = self.required.len() + self.optional.len();
+        match argument {
+            ActiveArgument::Positional(at) if *at < positional => Some(*at),
+            ActiveArgument::Positional(_) => {
+                self.rest.as_ref().map(|_| positional + self.keys.len())
+            }
+            ActiveArgument::Keyword(key) => self
+                .keys
+                .iter()
+                .position(|param| param == key)
+                .map(|at| positional + at),
+        }
+    }
+
+    pub fn max_positional(&self) -> Option<usize> {
+        match self.rest {
+            Some(_) => None,
+            None => Some(self.required.len() + self.optional.len()),
+        }
+    }
+}
+
+/// the argument of a call the cursor is on
+#[derive(Debug, Clone, PartialEq)]
+pub enum ActiveArgument {
+    Positional(usize),
+    Keyword(String),
+}
+
+/// the innermost call around the end of `text`, which is everything before the cursor
+pub fn call_at(text: &str) -> Option<(String, ActiveArgument)> {
+    struct Form {
+        /// the callee of `name(`, a `(name` form has it as its first item
+        name: Option<String>,
+        items: Vec<String>,
+    }
+    let mut forms = vec![Form {
+        name: None,
+        items: vec![],
+    }];
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if !(c.is_whitespace() || "()\";".contains(c)) {
+            word.get_or_insert_with(String::new).push(c);
+            continue;
+        }
+        let callee = word.take();
+        let items = &mut forms.last_mut().unwrap().items;
+        match c {
+            '(' => {
+                let name =
+                    callee.filter(|name| name.starts_with(|c: char| c.is_alphabetic() || c == '_'));
+                forms.push(Form {
+                    name,
+                    items: vec![],
+                });
+                continue;
+            }
+            '"' => {
+                items.extend(callee);
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+                items.push(String::new());
+                continue;
+            }
+            _ => items.extend(callee),
+        }
+        match c {
+            ';' => {
+                chars.by_ref().find(|c| *c == '\n');
+            }
+            ')' if forms.len() > 1 => {
+                forms.pop();
+                forms.last_mut().unwrap().items.push(String::new());
+            }
+            _ => {}
+        }
+    }
+
+    let innermost = forms.len() - 1;
+    forms.iter().enumerate().rev().find_map(|(at, form)| {
+        let (name, args) = match &form.name {
+            Some(name) => (name.clone(), &form.items[..]),
+            None if at > 0 => {
+                let (name, args) = form.items.split_first()?;
+                if !name.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+                    return None;
+                }
+                (name.clone(), args)
+            }
+            None => return None,
+        };
+        let mut positional = 0;
+        let mut keyword: Option<&str> = None;
+        for arg in args {
+            if let Some(key) = arg.strip_prefix('?') {
+                keyword = Some(key);
+            } else if keyword.take().is_none() {
+                positional += 1;
+            }
+        }
+        // the word being typed, unless the cursor is inside a nested form
+        let typing = word.as_deref().filter(|_| at == innermost);
+        let active = match (typing.and_then(|word| word.strip_prefix('?')), keyword) {
+            (Some(key), _) | (None, Some(key)) => ActiveArgument::Keyword(key.to_string()),
+            (None, None) => ActiveArgument::Positional(positional),
+        };
+        Some((name, active))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    use crate::token::Argument;
+    use crate::types::ValueType;
+
+    use super::{call_at, ActiveArgument, Signature};
+
+    #[test]
+    fn parse_signatures() {
+        let sig = Signature::parse("fun(a b @optional (c \"(x)\") d @rest more)").unwrap();
+        assert_eq!(sig.name, "fun");
+        assert_eq!(sig.required, vec!["a", "b"]);
+        assert_eq!(sig.optional, vec!["c", "d"]);
+        assert_eq!(sig.rest.as_deref(), Some("more"));
+        assert_eq!(sig.max_positional(), None);
+
+        let sig = Signature::parse("form(w @key (name 'x) prompt)").unwrap();
+        assert_eq!(sig.required, vec!["w"]);
+        assert_eq!(sig.keys, vec!["name", "prompt"]);
+        assert_eq!(sig.max_positional(), Some(1));
+
+        let sig = Signature::parse("now()").unwrap();
+        assert_eq!(
+            sig,
+            Signature {
+                name: "now".to_string(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(Signature::parse("broken"), None);
+    }
+
+    #[test]
+    fn leading_parameters_and_more_keys() {
+        let position = Position::default();
+        let range = Range::new(position, position);
+        let sig = Signature::parse("error([s_id] t_format @rest g_arguments)").unwrap();
+        assert_eq!(sig.label(), "error([s_id] t_format @rest g_arguments)");
+        assert_eq!(sig.required, vec!["t_format"]);
+
+        let with_id = [
+            Argument::Typed(ValueType::Symbol, range),
+            Argument::String("\"%d\"".to_string(), range),
+        ];
+        assert!(sig.binds_leading(&with_id));
+        assert_eq!(sig.for_call(&with_id).required, vec!["s_id", "t_format"]);
+        let without_id = [Argument::String("\"%d\"".to_string(), range)];
+        assert!(!sig.binds_leading(&without_id));
+        assert_eq!(sig.for_call(&without_id).required, vec!["t_format"]);
+
+        let sig = Signature::parse("form(@key name ...)").unwrap();
+        assert_eq!(sig.keys, vec!["name"]);
+        assert!(sig.more_keys);
+        assert_eq!(sig.label(), "form(@key name ...)");
+    }
+
+    #[test]
+    fn labels_and_active_parameters() {
+        let sig = Signature::parse("fun(a @optional (b 1) @key c d @rest e)").unwrap();
+        assert_eq!(sig.label(), "fun(a @optional b @key c d @rest e)");
+        assert_eq!(sig.parameters(), vec!["a", "b", "c", "d", "e"]);
+        assert_eq!(
+            sig.active_parameter(&ActiveArgument::Positional(1)),
+            Some(1)
+        );
+        assert_eq!(
+            sig.active_parameter(&ActiveArgument::Positional(5)),
+            Some(4)
+        );
+        assert_eq!(
+            sig.active_parameter(&ActiveArgument::Keyword("d".to_string())),
+            Some(3)
+        );
+        assert_eq!(
+            Signature::parse("now()")
+                .unwrap()
+                .active_parameter(&ActiveArgument::Positional(0)),
+            None
+        );
+    }
+
+    #[test]
+    fn calls_at_cursor() {
+        let positional = |at| ActiveArgument::Positional(at);
+        assert_eq!(
+            call_at("x = fun("),
+            Some(("fun".to_string(), positional(0)))
+        );
+        assert_eq!(
+            call_at("fun(a \"b)\" "),
+            Some(("fun".to_string(), positional(2)))
+        );
+        assert_eq!(
+            call_at("(fun a (g 1) b"),
+            Some(("fun".to_string(), positional(2)))
+        );
+        assert_eq!(
+            call_at("fun(a g(1 "),
+            Some(("g".to_string(), positional(1)))
+        );
+        assert_eq!(
+            call_at("fun(a '(1 "),
+            Some(("fun".to_string(), positional(1)))
+        );
+        assert_eq!(
+            call_at("fun(a ; b c\n ?key"),
+            Some((
+                "fun".to_string(),
+                ActiveArgument::Keyword("key".to_string())
+            ))
+        );
+        assert_eq!(
+            call_at("fun(?key "),
+            Some((
+                "fun".to_string(),
+                ActiveArgument::Keyword("key".to_string())
+            ))
+        );
+        assert_eq!(
+            call_at("fun(?key 1 b"),
+            Some(("fun".to_string(), positional(0)))
+        );
+        assert_eq!(call_at("fun(a) "), None);
+    }
+}
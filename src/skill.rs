@@ -1,191 +1,39 @@
-use log::info;
-use pest::error::{InputLocation, LineColLocation};
+use log::debug;
+use pest::error::InputLocation;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use regex::Regex;
-use std::borrow::Cow;
-use std::collections::VecDeque;
-use std::error::Error;
-use std::fs;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag, Position, Range};
+use std::collections::{BTreeMap, HashSet};
+use tower_lsp::lsp_types::{Diagnostic, Position, Range};
 
-use crate::token::{Token, TokenKind, TokenScope, TokenUse};
+use crate::builtins::builtin;
+use crate::docstring::{comment_text, string_text};
+use crate::encoding::{encoding, LineIndex};
+use crate::rules::SYNTAX_ERROR;
+use crate::token::{Argument, Token, TokenKind, TokenScope};
+use crate::types::ValueType;
 
 #[derive(Parser)]
 #[grammar = "skill.pest"]
 pub struct SkillParser;
 
-// fn parse_skill_pairs(pairs: &Pairs<Rule>) -> Vec<Result<Token, Diagnostic>> {
-//     let mut prev_docstring: Option<Pair<Rule>> = None;
-//     let mut collection: Vec<Result<Token, Diagnostic>> = vec![];
-//
-//     for pair in pairs {
-//         match pair.as_rule() {
-//             Rule::COMMENT => {
-//                 prev_docstring = Some(pair);
-//             }
-//             Rule::token => {
-//                 let (start_line, start_col) = pair.as_span().start_pos().line_col();
-//                 let (end_line, end_col) = pair.as_span().end_pos().line_col();
-//                 let docstring_start = Regex::new(r";;;\s*").unwrap();
-//                 let applicable_docstring = match prev_docstring.clone() {
-//                     Some(pair) => {
-//                         let prev_docstring_line = pair.as_span().end_pos().line_col().0;
-//                         if prev_docstring_line == start_line {
-//                             Some(docstring_start.replace_all(pair.as_str(), "").to_string())
-//                         } else {
-//                             None
-//                         }
-//                     }
-//                     None => None,
-//                 };
-//                 let name = pair.as_str().to_string();
-//                 if !vec!["t", "nil"].contains(&name.as_str()) {
-//                     let last_declaration = collection
-//                         .iter()
-//                         .filter(|tok| tok.is_ok())
-//                         .filter(|tok| {
-//                             tok.as_ref().unwrap().name == name
-//                                 && matches!(tok.as_ref().unwrap().usage, TokenUse::Declaration)
-//                         })
-//                         .rev()
-//                         .next()
-//                         .unwrap();
-//
-//                     // let last_instantiation = collection
-//                     //     .iter_mut()
-//                     //     .filter(|tok| tok.is_ok())
-//                     //     .filter(|tok| {
-//                     //         tok.as_ref().unwrap().name == name
-//                     //             && matches!(
-//                     //                 tok.as_ref().unwrap().usage,
-//                     //                 TokenUse::Instantiation { decl: _ }
-//                     //             )
-//                     //     })
-//                     //     .rev()
-//                     //     .next()
-//                     //     .unwrap();
-//
-//                     collection.push(Ok(Token {
-//                         name,
-//                         usage: TokenUse::Use {
-//                             decl: &last_declaration.unwrap().clone(),
-//                             inst: &last_declaration.unwrap().clone(),
-//                         },
-//                         kind: TokenKind::Variable,
-//                         scope: TokenScope::Global,
-//                         documentation: applicable_docstring,
-//                         encloses: None,
-//                         place: Range {
-//                             start: Position {
-//                                 line: start_line as u32,
-//                                 character: start_col as u32,
-//                             },
-//                             end: Position {
-//                                 line: end_line as u32,
-//                                 character: end_col as u32,
-//                             },
-//                         },
-//                     }));
-//                 } else {
-//                 }
-//             }
-//             Rule::skill => {
-//                 collection.append(parse_skill_pairs(pair.into_inner()));
-//             }
-//             Rule::list => {
-//                 collection.append(parse_skill_pairs(pair.into_inner()).as_mut());
-//             }
-//             Rule::cstyle_list => {
-//                 collection.append(parse_skill_pairs(pair.into_inner()).as_mut());
-//             }
-//             Rule::assign => {
-//                 let (start_line, start_col) = pair.as_span().start_pos().line_col();
-//                 let (end_line, end_col) = pair.as_span().end_pos().line_col();
-//                 let name_rule = pair.into_inner().next().unwrap();
-//                 let name = name_rule.as_str().to_string();
-//                 let docstring_start = Regex::new(r";;;\s*").unwrap();
-//                 let applicable_docstring = match prev_docstring.clone() {
-//                     Some(pair) => {
-//                         let prev_docstring_line = pair.as_span().end_pos().line_col().0;
-//                         if prev_docstring_line == start_line {
-//                             Some(docstring_start.replace_all(pair.as_str(), "").to_string())
-//                         } else {
-//                             None
-//                         }
-//                     }
-//                     None => None,
-//                 };
-//                 let last_declaration = collection
-//                     .iter()
-//                     .filter(|tok| {
-//                         tok.is_ok()
-//                             && (tok.unwrap().name == name)
-//                             && matches!(tok.unwrap().usage, TokenUse::Declaration)
-//                     })
-//                     .rev()
-//                     .next()
-//                     .unwrap()
-//                     .unwrap();
-//                 collection.push(Ok(Token {
-//                     name: name,
-//                     usage: TokenUse::Instantiation {
-//                         decl: &last_declaration,
-//                     },
-//                     kind: TokenKind::Variable,
-//                     scope: TokenScope::Global,
-//                     documentation: applicable_docstring,
-//                     encloses: None,
-//                     place: Range {
-//                         start: Position {
-//                             line: start_line as u32,
-//                             character: start_col as u32,
-//                         },
-//                         end: Position {
-//                             line: end_line as u32,
-//                             character: end_col as u32,
-//                         },
-//                     },
-//                 }))
-//             }
-//             _ => {
-//                 println!(
-//                     "unhandled {:?}: ({},{}) -> ({},{})",
-//                     pair.as_rule(),
-//                     pair.as_span().start_pos().line_col().0,
-//                     pair.as_span().start_pos().line_col().1,
-//                     pair.as_span().end_pos().line_col().0,
-//                     pair.as_span().end_pos().line_col().1
-//                 );
-//             }
-//         };
-//     }
-//
-//     collection
-// }
-//
-
-fn start_position_of_pair(pair: &Pair<Rule>) -> Position {
-    let (line, character) = pair.as_span().start_pos().line_col();
-
-    Position {
-        line: line as u32 - 1,
-        character: character as u32 - 1,
-    }
+/// `Position` is not `Hash`, sets of positions use this instead
+fn position_key(pos: Position) -> (u32, u32) {
+    (pos.line, pos.character)
 }
-fn end_position_of_pair(pair: &Pair<Rule>) -> Position {
-    let (line, character) = pair.as_span().end_pos().line_col();
 
-    Position {
-        line: line as u32 - 1,
-        character: character as u32 - 1,
-    }
+fn start_position_of_pair(pair: &Pair<Rule>, index: &LineIndex) -> Position {
+    index.position(pair.as_span().start())
+}
+
+fn end_position_of_pair(pair: &Pair<Rule>, index: &LineIndex) -> Position {
+    index.position(pair.as_span().end())
 }
 
-fn range_of_pair(pair: &Pair<Rule>) -> Range {
+fn range_of_pair(pair: &Pair<Rule>, index: &LineIndex) -> Range {
     Range {
-        start: start_position_of_pair(pair),
-        end: end_position_of_pair(pair),
+        start: start_position_of_pair(pair, index),
+        end: end_position_of_pair(pair, index),
     }
 }
 
@@ -194,45 +42,17 @@ fn parse_skill_pairs(pairs: Pairs<Rule>) -> Vec<Pair<Rule>> {
 
     for pair in pairs {
         match pair.as_rule() {
-            Rule::COMMENT | Rule::token => collection.push(pair),
-            Rule::skill | Rule::assign => {
+            Rule::COMMENT | Rule::token | Rule::callee => collection.push(pair),
+            Rule::skill | Rule::assign | Rule::inline_expr => {
                 collection.push(pair.clone());
                 collection.append(parse_skill_pairs(pair.into_inner()).as_mut())
             }
             Rule::list | Rule::cstyle_list => {
                 collection.push(pair.clone());
                 collection.append(parse_skill_pairs(pair.into_inner()).as_mut())
-                // let inners = pair.into_inner();
-                // match inners.peek() {
-                //     Some(first_inner_pair) => match first_inner_pair.as_rule() {
-                //         Rule::keywords => {
-                //             let parser: fn(Pairs<Rule>) -> Vec<Pair<Rule>> =
-                //                 inners.peek().map_or(parse_skill_pairs, |keyword| {
-                //                     match keyword.as_str() {
-                //                         "let" => parse_let,
-                //                         _ => parse_skill_pairs,
-                //                     }
-                //                 });
-                //             collection.append(parser(inners).as_mut());
-                //         }
-                //         _ => {
-                //             collection.append(parse_skill_pairs(inners).as_mut());
-                //         }
-                //     },
-                //     None => {
-                //         collection.append(parse_skill_pairs(inners).as_mut());
-                //     }
-                // }
             }
             _ => {
-                println!(
-                    "unhandled {:?}: ({},{}) -> ({},{})",
-                    pair.as_rule(),
-                    pair.as_span().start_pos().line_col().0,
-                    pair.as_span().start_pos().line_col().1,
-                    pair.as_span().end_pos().line_col().0,
-                    pair.as_span().end_pos().line_col().1
-                );
+                debug!("unhandled {:?}: {:?}", pair.as_rule(), pair.as_span());
             }
         };
     }
@@ -240,6 +60,89 @@ fn parse_skill_pairs(pairs: Pairs<Rule>) -> Vec<Pair<Rule>> {
     collection
 }
 
+/// splits a list into the pair naming the called function and its arguments,
+/// works for both `(fun arg)` and `fun(arg)` while quoted lists have no head
+fn list_head<'i>(pair: &Pair<'i, Rule>) -> Option<(Pair<'i, Rule>, Vec<Pair<'i, Rule>>)> {
+    if pair.as_str().starts_with('\'') {
+        return None;
+    }
+
+    let mut inner = pair.clone().into_inner();
+    let first = inner.next()?;
+    match first.as_rule() {
+        Rule::cstyle_list => {
+            let mut call = first.into_inner();
+            let head = call.next()?;
+            Some((head, call.collect()))
+        }
+        Rule::token | Rule::keywords => Some((first, inner.collect())),
+        _ => None,
+    }
+}
+
+/// the functions infix operators call
+fn operator_function(operator: &str) -> &'static str {
+    match operator {
+        "+" => "plus",
+        "-" => "difference",
+        "*" => "times",
+        _ => "quotient",
+    }
+}
+
+/// the type of what `pair` evaluates to as far as it is known without
+/// looking at other forms, so variables are left out
+fn value_type(pair: &Pair<Rule>) -> Option<ValueType> {
+    match pair.as_rule() {
+        Rule::literal => ValueType::of_literal(pair.as_str()),
+        Rule::list if pair.as_str().starts_with('\'') => Some(ValueType::List),
+        Rule::list => match list_head(pair)?.0.as_str() {
+            "lambda" => Some(ValueType::Procedure),
+            name => builtin(name)?.signature.returns,
+        },
+        Rule::inline_expr => {
+            let operands: Vec<Option<ValueType>> = pair
+                .clone()
+                .into_inner()
+                .filter(|operand| operand.as_rule() != Rule::inline_operator)
+                .map(|operand| value_type(&operand))
+                .collect();
+            ValueType::of_arithmetic(&operands).or(Some(ValueType::Number))
+        }
+        _ => None,
+    }
+}
+
+/// the arguments of a call, `?name value` pairs become keyword arguments
+fn call_arguments(args: &[Pair<Rule>], index: &LineIndex) -> Vec<Argument> {
+    let mut arguments = vec![];
+    let mut args = args.iter().filter(|arg| arg.as_rule() != Rule::COMMENT);
+
+    while let Some(arg) = args.next() {
+        let place = range_of_pair(arg, index);
+        if arg.as_rule() == Rule::keyword_arg {
+            let end = args
+                .next()
+                .map_or(place.end, |value| end_position_of_pair(value, index));
+            arguments.push(Argument::Keyword(
+                arg.as_str().trim_start_matches('?').to_string(),
+                Range::new(place.start, end),
+            ));
+        } else if arg.as_rule() == Rule::literal && arg.as_str().starts_with('"') {
+            arguments.push(Argument::String(arg.as_str().to_string(), place));
+        } else if let Some(value_type) = value_type(arg) {
+            arguments.push(Argument::Typed(value_type, place));
+        } else {
+            arguments.push(Argument::Positional(place));
+        }
+    }
+
+    arguments
+}
+
+/// what `foreach` can map with instead of iterating
+const MAPPING_FUNCTIONS: [&str; 5] = ["mapc", "mapcar", "mapcan", "map", "maplist"];
+
 fn variable_declaration(name: &str, scope: Range, info: &str, place: Range) -> Token {
     Token {
         kind: TokenKind::VariableAssignment,
@@ -248,31 +151,38 @@ fn variable_declaration(name: &str, scope: Range, info: &str, place: Range) -> T
         name: name.to_string(),
         info: Some(info.to_string()),
         encloses: None,
+        arguments: vec![],
+        value_type: None,
         place,
     }
 }
 
-fn parse_scoped_vars(pairs: Pairs<Rule>, scope: &Range) -> Vec<Token> {
+fn parse_scoped_vars(pairs: Pairs<Rule>, scope: &Range, index: &LineIndex) -> Vec<Token> {
     let mut passed_assigns = vec![];
 
     for p in pairs {
         match p.as_rule() {
             Rule::list => {
                 let info = p.as_str();
-                let f = p.clone().into_inner().peek().unwrap();
-                passed_assigns.push(variable_declaration(
-                    f.as_str(),
-                    scope.to_owned(),
-                    info,
-                    range_of_pair(&p),
-                ))
+                let mut inner = p.clone().into_inner();
+                // `let((()) ...)` binds nothing
+                let Some(f) = inner.next() else { continue };
+                passed_assigns.push(Token {
+                    value_type: inner.next().and_then(|value| value_type(&value)),
+                    ..variable_declaration(
+                        f.as_str(),
+                        scope.to_owned(),
+                        info,
+                        range_of_pair(&f, index),
+                    )
+                })
             }
             Rule::token => {
                 passed_assigns.push(variable_declaration(
                     p.as_str(),
                     scope.to_owned(),
                     format!("({} nil)", p.as_str()).as_str(),
-                    range_of_pair(&p),
+                    range_of_pair(&p, index),
                 ));
             }
             _ => {}
@@ -282,64 +192,109 @@ fn parse_scoped_vars(pairs: Pairs<Rule>, scope: &Range) -> Vec<Token> {
     passed_assigns
 }
 
-fn parse_flat_pairs(pairs: Vec<Pair<Rule>>) -> (Vec<Token>, Vec<Diagnostic>) {
-    let mut last_comment: (Position, Cow<str>) = (
+fn parse_flat_pairs(pairs: Vec<Pair<Rule>>, index: &LineIndex) -> (Vec<Token>, Vec<Diagnostic>) {
+    let mut last_comment: (Position, String) = (
         Position {
             line: 100000000,
             character: 0,
         },
-        Cow::Owned("".to_string()),
+        String::new(),
     );
-    let mut active_scope: Option<Range> = None;
     let mut parsed_tokens = vec![];
-    let mut parsed_declarations = vec![];
-    let mut parsed_errors = vec![];
-    let docstring_start = Regex::new(r";;;\s*").unwrap();
+    let parsed_errors = vec![];
+    // names that are declared (parameters, let bindings, procedure names) instead of used
+    let mut binding_sites: HashSet<(u32, u32)> = HashSet::new();
+    // local bindings seen so far, assigning to them does not create a global
+    let mut locals: Vec<(String, Range)> = vec![];
+    // heads of `(fun arg)` style calls, these name functions instead of variables
+    let mut call_heads: HashSet<(u32, u32)> = HashSet::new();
+    // arguments of the calls by the start of the function name
+    let mut calls: BTreeMap<Position, Vec<Argument>> = BTreeMap::new();
+    // words of syntax forms like `then` or `go` labels, neither variables nor calls
+    let mut syntax_words: HashSet<(u32, u32)> = HashSet::new();
+    let suppression =
+        Regex::new(r"^;+\s*srls-(disable-next-line|disable-file|disable|enable)(?:\s+(.*))?$")
+            .unwrap();
 
     for pair in pairs {
         match pair.as_rule() {
             Rule::COMMENT => {
-                if pair.as_str().starts_with(";;;") {
-                    let corrected_docstring = docstring_start.replace_all(pair.as_str(), "");
-                    last_comment = (end_position_of_pair(&pair), corrected_docstring);
-                    println!("{:?}", last_comment.clone());
+                let comment = pair.as_str().trim_end();
+                if let Some(directive) = suppression.captures(comment) {
+                    let start = pair.as_span().start();
+                    let place = Range {
+                        start: index.position(start),
+                        end: index.position(start + comment.len()),
+                    };
+                    let rules = directive.get(2).map_or("", |rules| rules.as_str());
+                    parsed_tokens.push(Token {
+                        kind: TokenKind::Suppression,
+                        scope: TokenScope::Global(place.start),
+                        name: directive[1].to_string(),
+                        info: Some(rules.replace(',', " ").trim().to_string()),
+                        documentation: None,
+                        encloses: None,
+                        place,
+                        arguments: vec![],
+                        value_type: None,
+                    });
+                } else if pair.as_str().starts_with(";;;") {
+                    let text = comment_text(pair.as_str());
+                    // indented blocks are a comment per line
+                    let docstring =
+                        if last_comment.0.line == start_position_of_pair(&pair, index).line {
+                            format!("{}\n{text}", last_comment.1)
+                        } else {
+                            text
+                        };
+                    last_comment = (end_position_of_pair(&pair, index), docstring);
+                    debug!("{:?}", last_comment.clone());
                 }
             }
-            Rule::token => {
-                let name = pair.as_str().to_string();
+            Rule::token | Rule::callee => {
+                let place = range_of_pair(&pair, index);
+                if binding_sites.contains(&position_key(place.start))
+                    || syntax_words.contains(&position_key(place.start))
+                {
+                    continue;
+                }
+                let kind = if pair.as_rule() == Rule::callee
+                    || call_heads.contains(&position_key(place.start))
+                {
+                    TokenKind::FunctionCall
+                } else {
+                    TokenKind::VariableUse
+                };
+                let arguments = calls.remove(&place.start).unwrap_or_default();
                 parsed_tokens.push(Token {
-                    kind: TokenKind::VariableUse,
-                    scope: TokenScope::Global(end_position_of_pair(&pair)),
-                    name: name.clone(),
+                    kind,
+                    scope: TokenScope::Global(place.end),
+                    name: pair.as_str().to_string(),
                     info: None,
                     documentation: None,
                     encloses: None,
-                    place: range_of_pair(&pair),
+                    arguments,
+                    value_type: None,
+                    place,
                 });
-
-                if !parsed_declarations.contains(&name) {
-                    parsed_errors.push(Diagnostic {
-                        range: range_of_pair(&pair),
-                        severity: Some(DiagnosticSeverity::ERROR),
-                        code: None,
-                        code_description: None,
-                        source: Some("srls".to_string()),
-                        message: "variable used before declaration".to_string(),
-                        related_information: None,
-                        tags: None,
-                        data: None,
-                    })
-                }
             }
             Rule::assign => {
-                let range = range_of_pair(&pair);
+                let range = range_of_pair(&pair, index);
                 let info = Some(pair.as_str().to_string());
-                let assigned_to = pair.into_inner().next().unwrap();
+                let mut inner = pair.into_inner();
+                let assigned_to = inner.next().unwrap();
+                let assigned = inner.next();
                 let name = assigned_to.as_str().to_string();
-                parsed_declarations.push(name.clone());
+                binding_sites.insert(position_key(start_position_of_pair(&assigned_to, index)));
+                let local = locals.iter().rev().find(|(local, scope)| {
+                    *local == name && scope.start <= range.start && range.end <= scope.end
+                });
                 parsed_tokens.push(Token {
                     kind: TokenKind::VariableAssignment,
-                    scope: TokenScope::Global(range.end),
+                    scope: match local {
+                        Some((_, scope)) => TokenScope::Local(*scope),
+                        None => TokenScope::Global(range.end),
+                    },
                     info,
                     name,
                     documentation: if last_comment.0.line == range.start.line {
@@ -348,88 +303,222 @@ fn parse_flat_pairs(pairs: Vec<Pair<Rule>>) -> (Vec<Token>, Vec<Diagnostic>) {
                         None
                     },
                     encloses: None,
+                    arguments: vec![],
+                    value_type: assigned.and_then(|value| value_type(&value)),
                     place: range,
                 });
             }
+            Rule::inline_expr => {
+                // `a + b` is `plus(a b)`, every operator is a call of its neighbours
+                let inner: Vec<Pair<Rule>> = pair.into_inner().collect();
+                for (at, operator) in inner.iter().enumerate() {
+                    if operator.as_rule() != Rule::inline_operator {
+                        continue;
+                    }
+                    parsed_tokens.push(Token {
+                        kind: TokenKind::FunctionCall,
+                        scope: TokenScope::Global(end_position_of_pair(operator, index)),
+                        name: operator_function(operator.as_str()).to_string(),
+                        info: None,
+                        documentation: None,
+                        encloses: None,
+                        arguments: call_arguments(&inner[at - 1..=at + 1], index),
+                        value_type: None,
+                        place: range_of_pair(operator, index),
+                    });
+                }
+            }
             Rule::list => {
-                let range = range_of_pair(&pair);
+                let range = range_of_pair(&pair, index);
                 let info = pair
                     .as_str()
-                    .split("\n")
+                    .split('\n')
                     .next()
                     .map(|info| info.to_string());
+                let documentation = if last_comment.0.line == range.start.line {
+                    Some(last_comment.1.to_string())
+                } else {
+                    None
+                };
 
-                match pair.clone().into_inner().peek().map_or(None, |first| {
-                    let kw = first.as_str();
-                    match kw {
-                        "let" => Some(kw),
-                        _ => None,
-                    }
-                }) {
-                    Some(_) => {
+                let Some((head, args)) = list_head(&pair) else {
+                    continue;
+                };
+                if head.as_rule() == Rule::token {
+                    call_heads.insert(position_key(start_position_of_pair(&head, index)));
+                }
+                calls.insert(
+                    start_position_of_pair(&head, index),
+                    call_arguments(&args, index),
+                );
+
+                match head.as_str() {
+                    "let" | "prog" => {
                         parsed_tokens.push(Token {
                             kind: TokenKind::LetBlock,
                             scope: TokenScope::Local(range),
                             info,
-                            name: format!("let:{line}", line = range.start.line),
-                            documentation: if last_comment.0.line == range.start.line {
-                                Some(last_comment.1.to_string())
-                            } else {
-                                None
-                            },
+                            name: format!("{}:{}", head.as_str(), range.start.line),
+                            documentation,
                             encloses: Some(range),
+                            arguments: vec![],
+                            value_type: None,
                             place: range,
                         });
 
-                        active_scope = Some(range);
+                        if let Some(bindings) = args.first() {
+                            let mut variables =
+                                parse_scoped_vars(bindings.clone().into_inner(), &range, index);
+                            binding_sites
+                                .extend(variables.iter().map(|tok| position_key(tok.place.start)));
+                            locals.extend(variables.iter().map(|tok| (tok.name.clone(), range)));
+                            parsed_tokens.append(variables.as_mut());
+                        }
+                        if head.as_str() == "prog" {
+                            // bare words in the body are labels `go` jumps to
+                            syntax_words.extend(
+                                args.iter()
+                                    .skip(1)
+                                    .filter(|arg| arg.as_rule() == Rule::token)
+                                    .map(|arg| position_key(start_position_of_pair(arg, index))),
+                            );
+                        }
+                    }
+                    "go" => {
+                        syntax_words.extend(
+                            args.iter()
+                                .filter(|arg| arg.as_rule() == Rule::token)
+                                .map(|arg| position_key(start_position_of_pair(arg, index))),
+                        );
                     }
-                    None => match active_scope {
-                        Some(scope) => {
-                            let mut variables = parse_scoped_vars(pair.into_inner(), &scope);
-
-                            parsed_declarations.append(
-                                variables
-                                    .clone()
-                                    .iter()
-                                    .map(|tok| tok.name.clone())
-                                    .collect::<Vec<_>>()
-                                    .as_mut(),
+                    "if" => {
+                        // `if(test then ... else ...)`
+                        syntax_words.extend(
+                            args.iter()
+                                .filter(|arg| arg.as_rule() == Rule::token)
+                                .filter(|arg| matches!(arg.as_str(), "then" | "else"))
+                                .map(|arg| position_key(start_position_of_pair(arg, index))),
+                        );
+                    }
+                    "case" | "caseq" => {
+                        // the values a clause matches are not evaluated
+                        for clause in args
+                            .iter()
+                            .skip(1)
+                            .filter(|arg| arg.as_rule() == Rule::list)
+                        {
+                            let Some(values) = clause.clone().into_inner().next() else {
+                                continue;
+                            };
+                            syntax_words.extend(
+                                std::iter::once(values.clone())
+                                    .chain(values.into_inner().flatten())
+                                    .filter(|value| value.as_rule() == Rule::token)
+                                    .map(|value| {
+                                        position_key(start_position_of_pair(&value, index))
+                                    }),
                             );
+                        }
+                    }
+                    "lambda" => {
+                        if let Some(params) = args.first().filter(|arg| arg.as_rule() == Rule::list)
+                        {
+                            let mut variables =
+                                parse_scoped_vars(params.clone().into_inner(), &range, index);
+                            binding_sites
+                                .extend(variables.iter().map(|tok| position_key(tok.place.start)));
+                            locals.extend(variables.iter().map(|tok| (tok.name.clone(), range)));
                             parsed_tokens.append(variables.as_mut());
-
-                            active_scope = None;
                         }
-                        None => {
-                            // parsed_tokens.push(Token {
-                            //     kind: TokenKind::List,
-                            //     scope: TokenScope::Local(range),
-                            //     info,
-                            //     name: format!("list:{line}", line = range.start.line),
-                            //     documentation: if last_comment.0.line == range.start.line {
-                            //         Some(last_comment.1.to_string())
-                            //     } else {
-                            //         None
-                            //     },
-                            //     encloses: Some(range),
-                            //     place: range,
-                            // });
+                    }
+                    "foreach" | "for" | "setof" | "exists" | "forall" => {
+                        // `foreach(mapcar x list ...)` names the mapping function first
+                        syntax_words.extend(
+                            args.first()
+                                .filter(|arg| arg.as_rule() == Rule::token)
+                                .filter(|arg| MAPPING_FUNCTIONS.contains(&arg.as_str()))
+                                .map(|arg| position_key(start_position_of_pair(arg, index))),
+                        );
+                        let variable = args
+                            .iter()
+                            .take(2)
+                            .find(|arg| !MAPPING_FUNCTIONS.contains(&arg.as_str()))
+                            .filter(|arg| arg.as_rule() == Rule::token);
+                        if let Some(variable) = variable {
+                            let place = range_of_pair(variable, index);
+                            binding_sites.insert(position_key(place.start));
+                            locals.push((variable.as_str().to_string(), range));
+                            parsed_tokens.push(variable_declaration(
+                                variable.as_str(),
+                                range,
+                                info.as_deref().unwrap_or_default(),
+                                place,
+                            ));
                         }
-                    },
-                };
+                    }
+                    "procedure" | "nprocedure" => {
+                        let Some(signature) = args
+                            .first()
+                            .and_then(|arg| arg.clone().into_inner().next())
+                            .filter(|arg| arg.as_rule() == Rule::cstyle_list)
+                        else {
+                            continue;
+                        };
+                        let mut params = signature.clone().into_inner();
+                        let name = params.next().unwrap();
+                        // a string first in a body of several forms documents the procedure
+                        let body_docstring = args
+                            .get(1)
+                            .filter(|arg| arg.as_rule() == Rule::literal && args.len() > 2)
+                            .filter(|arg| arg.as_str().starts_with('"'))
+                            .map(|arg| string_text(arg.as_str()));
+                        let documentation = match (documentation, body_docstring) {
+                            (Some(comment), Some(body)) => Some(format!("{comment}\n\n{body}")),
+                            (comment, body) => comment.or(body),
+                        };
+                        parsed_tokens.push(Token {
+                            kind: TokenKind::Function,
+                            scope: TokenScope::Global(range.end),
+                            info: Some(signature.as_str().to_string()),
+                            name: name.as_str().to_string(),
+                            documentation,
+                            encloses: Some(range),
+                            arguments: vec![],
+                            value_type: Some(ValueType::Procedure),
+                            place: range_of_pair(&name, index),
+                        });
+                        binding_sites.insert(position_key(start_position_of_pair(&name, index)));
 
-                // println!("");
-                // println!(
-                //     "let: {}, {:?}",
-                //     first_pair(&pair.clone().into_inner()),
-                //     pair.clone()
-                // );
-                // println!("");
+                        let mut variables = parse_scoped_vars(params, &range, index);
+                        binding_sites
+                            .extend(variables.iter().map(|tok| position_key(tok.place.start)));
+                        locals.extend(variables.iter().map(|tok| (tok.name.clone(), range)));
+                        parsed_tokens.append(variables.as_mut());
+                    }
+                    "load" | "loadi" => {
+                        if let Some(file) = args.first().filter(|arg| arg.as_str().starts_with('"'))
+                        {
+                            parsed_tokens.push(Token {
+                                kind: TokenKind::Load,
+                                scope: TokenScope::Global(range.end),
+                                info,
+                                name: file.as_str().trim_matches('"').to_string(),
+                                documentation: None,
+                                encloses: None,
+                                arguments: vec![],
+                                value_type: None,
+                                place: range,
+                            });
+                        }
+                    }
+                    _ => {}
+                }
             }
             _ => {
-                println!(
+                debug!(
                     "unhandled {:?} ({:?})",
                     pair.as_rule(),
-                    range_of_pair(&pair)
+                    range_of_pair(&pair, index)
                 );
             }
         }
@@ -438,42 +527,81 @@ fn parse_flat_pairs(pairs: Vec<Pair<Rule>>) -> (Vec<Token>, Vec<Diagnostic>) {
     (parsed_tokens, parsed_errors)
 }
 
-pub fn parse_skill_content(content: &str) -> (Vec<Token>, Vec<Diagnostic>) {
-    match SkillParser::parse(Rule::skill, &content) {
-        Ok(file) => {
-            let flat_tokens = parse_skill_pairs(file);
-            parse_flat_pairs(flat_tokens)
+/// top-level forms on shared lines, together with a docstring right above them,
+/// these can be parsed on their own without changing the result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chunk {
+    pub first_line: u32,
+    pub last_line: u32,
+    /// ends with a comment that would document a form on the next line
+    pub trailing_comment: bool,
+}
+
+fn chunks_of_pairs(pairs: Pairs<Rule>, index: &LineIndex) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = vec![];
+
+    for pair in pairs.filter(|pair| pair.as_rule() != Rule::EOI) {
+        let range = range_of_pair(&pair, index);
+        let first_line = range.start.line;
+        // comments end after their newline
+        let last_line = if range.end.character == 0 && range.end.line > first_line {
+            range.end.line - 1
+        } else {
+            range.end.line
+        };
+        let is_comment = pair.as_rule() == Rule::COMMENT;
+
+        match chunks.last_mut() {
+            Some(chunk)
+                if first_line <= chunk.last_line
+                    || (chunk.trailing_comment && first_line == chunk.last_line + 1) =>
+            {
+                chunk.last_line = chunk.last_line.max(last_line);
+                chunk.trailing_comment = is_comment;
+            }
+            _ => chunks.push(Chunk {
+                first_line,
+                last_line,
+                trailing_comment: is_comment,
+            }),
         }
-        Err(e) => (
-            vec![],
-            vec![Diagnostic {
-                range: match e.line_col {
-                    LineColLocation::Pos((line, col)) => Range {
-                        start: Position {
-                            line: line as u32 - 1,
-                            character: col as u32 - 1,
-                        },
-                        end: Position {
-                            line: line as u32 - 1,
-                            character: col as u32 - 1,
-                        },
-                    },
-                    LineColLocation::Span((from_line, from_col), (to_line, to_col)) => Range {
-                        start: Position {
-                            line: from_line as u32 - 1,
-                            character: from_col as u32 - 1,
-                        },
-                        end: Position {
-                            line: to_line as u32 - 1,
-                            character: to_col as u32 - 1,
-                        },
-                    },
-                },
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: "invalid syntax".to_string(),
-                ..Default::default()
-            }],
-        ),
+    }
+
+    chunks
+}
+
+fn syntax_error(e: pest::error::Error<Rule>, index: &LineIndex) -> Diagnostic {
+    let (start, end) = match e.location {
+        InputLocation::Pos(at) => (at, at),
+        InputLocation::Span(span) => span,
+    };
+    SYNTAX_ERROR.diagnostic(
+        Range {
+            start: index.position(start),
+            end: index.position(end),
+        },
+        "invalid syntax".to_string(),
+    )
+}
+
+/// parses `content` into its tokens and the chunks they were found in
+pub fn parse_skill_chunks(content: &str) -> Result<(Vec<Token>, Vec<Chunk>), Box<Diagnostic>> {
+    let index = LineIndex::new(content, encoding());
+    let file =
+        SkillParser::parse(Rule::skill, content).map_err(|e| Box::new(syntax_error(e, &index)))?;
+    let chunks = file
+        .clone()
+        .next()
+        .map(|skill| chunks_of_pairs(skill.into_inner(), &index))
+        .unwrap_or_default();
+    let (tokens, _) = parse_flat_pairs(parse_skill_pairs(file), &index);
+    Ok((tokens, chunks))
+}
+
+pub fn parse_skill_content(content: &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    match parse_skill_chunks(content) {
+        Ok((tokens, _)) => (tokens, vec![]),
+        Err(err) => (vec![], vec![*err]),
     }
 }
 
@@ -481,7 +609,12 @@ pub fn parse_skill_content(content: &str) -> (Vec<Token>, Vec<Diagnostic>) {
 mod tests {
     use std::fs;
 
-    use super::parse_skill_content;
+    use tower_lsp::lsp_types::Position;
+
+    use crate::token::TokenKind;
+    use crate::types::ValueType;
+
+    use super::{parse_skill_chunks, parse_skill_content, Chunk};
 
     #[test]
     fn parse_testing_file() {
@@ -495,4 +628,83 @@ mod tests {
             println!("{:?}", error);
         }
     }
+
+    #[test]
+    fn chunks_follow_top_level_forms() {
+        let (_, chunks) =
+            parse_skill_chunks("a = 1 b = 2\n;;; doc\nc = list(1\n  2)\n\n; plain\n\nd = 3\n")
+                .unwrap();
+        let lines: Vec<(u32, u32)> = chunks
+            .iter()
+            .map(|chunk| (chunk.first_line, chunk.last_line))
+            .collect();
+
+        assert_eq!(lines, vec![(0, 0), (1, 3), (5, 5), (7, 7)]);
+        assert_eq!(
+            chunks[2],
+            Chunk {
+                first_line: 5,
+                last_line: 5,
+                trailing_comment: true
+            }
+        );
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let (tokens, _) = parse_skill_content(";;; 5µs delay 𝄞\ndelay = \"𝄞\" late = delay\n");
+        let late = tokens.iter().find(|tok| tok.name == "late").unwrap();
+        let used = tokens.iter().rfind(|tok| tok.name == "delay").unwrap();
+
+        assert_eq!(late.place.start, Position::new(1, 13));
+        assert_eq!(used.place.end, Position::new(1, 25));
+        assert_eq!(tokens[0].documentation.as_deref(), Some("5µs delay 𝄞"));
+    }
+
+    #[test]
+    fn empty_bindings() {
+        let (tokens, errors) = parse_skill_content("let((()) 1)\nlambda((()) 2)\n");
+        assert!(errors.is_empty());
+        assert!(tokens
+            .iter()
+            .all(|tok| tok.kind != TokenKind::VariableAssignment));
+    }
+
+    #[test]
+    fn scaled_numbers() {
+        let (tokens, errors) = parse_skill_content("r = 10k\nc = 1.5u\nn = 3\n");
+        assert!(errors.is_empty());
+        let types: Vec<Option<ValueType>> = tokens.iter().map(|tok| tok.value_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                Some(ValueType::Flonum),
+                Some(ValueType::Flonum),
+                Some(ValueType::Fixnum)
+            ]
+        );
+        assert!(!parse_skill_content("x = 10q\n").1.is_empty());
+    }
+
+    #[test]
+    fn docstrings() {
+        let (tokens, errors) = parse_skill_content(
+            ";;; Sums up.\n;;;\n;;; - twice\nprocedure(sum(a b)\n  \"@param a number\n   first\"\n  plus(a b)\n)\n\
+             procedure(name() \"only the result\")\n  ;;; indented\n  ;;; block\n  x = 1\n",
+        );
+        assert!(errors.is_empty());
+        let documentation = |name| {
+            tokens
+                .iter()
+                .find(|tok| tok.name == name)
+                .and_then(|tok| tok.documentation.as_deref())
+        };
+
+        assert_eq!(
+            documentation("sum"),
+            Some("Sums up.\n\n- twice\n\n@param a number\nfirst")
+        );
+        assert_eq!(documentation("name"), None);
+        assert_eq!(documentation("x"), Some("indented\nblock"));
+    }
 }
@@ -1,8 +1,13 @@
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemKind, DocumentSymbol, Documentation, Position, Range, SymbolKind,
+    CompletionItem, CompletionItemKind, DocumentSymbol, Documentation, Location, MarkupContent,
+    MarkupKind, Position, Range, SymbolInformation, SymbolKind, Url,
 };
 
-#[derive(Debug, Clone)]
+use crate::docstring::Docstring;
+use crate::types::ValueType;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TokenScope {
     Global(Position),
     Local(Range),
@@ -17,6 +22,7 @@ impl TokenScope {
     }
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum TokenUse<'a> {
     Declaration,
@@ -24,14 +30,20 @@ pub enum TokenUse<'a> {
     Use { decl: &'a Token, inst: &'a Token },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum TokenKind {
     VariableAssignment,
     VariableUse,
     Function,
+    FunctionCall,
+    #[allow(dead_code)]
     Struct,
+    #[allow(dead_code)]
     List,
     LetBlock,
+    Load,
+    /// `; srls-disable-next-line RULE` and the like, the rules are kept in `info`
+    Suppression,
 }
 
 impl TokenKind {
@@ -41,8 +53,11 @@ impl TokenKind {
             TokenKind::Function => Some(CompletionItemKind::FUNCTION),
             TokenKind::Struct => Some(CompletionItemKind::STRUCT),
             TokenKind::VariableUse => Some(CompletionItemKind::VARIABLE),
+            TokenKind::FunctionCall => Some(CompletionItemKind::FUNCTION),
             TokenKind::List => None,
             TokenKind::LetBlock => None,
+            TokenKind::Load => Some(CompletionItemKind::FILE),
+            TokenKind::Suppression => None,
         }
     }
 
@@ -52,13 +67,16 @@ impl TokenKind {
             TokenKind::Function => SymbolKind::FUNCTION,
             TokenKind::Struct => SymbolKind::STRUCT,
             TokenKind::VariableUse => SymbolKind::VARIABLE,
+            TokenKind::FunctionCall => SymbolKind::FUNCTION,
             TokenKind::List => SymbolKind::ARRAY,
             TokenKind::LetBlock => SymbolKind::NAMESPACE,
+            TokenKind::Load => SymbolKind::FILE,
+            TokenKind::Suppression => SymbolKind::NULL,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
     /// kind of token
     pub kind: TokenKind,
@@ -78,6 +96,41 @@ pub struct Token {
 
     /// place of declaration for the token (most likely the line)
     pub place: Range,
+
+    /// what a function call passes
+    #[serde(default)]
+    pub arguments: Vec<Argument>,
+
+    /// what an assignment assigns or a definition defines, as far as it is known
+    #[serde(default)]
+    pub value_type: Option<ValueType>,
+}
+
+/// an argument of a function call
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Argument {
+    Positional(Range),
+    /// a positional string literal as written, with its quotes and escapes
+    String(String, Range),
+    /// a positional argument of a type known without looking at other forms
+    Typed(ValueType, Range),
+    /// `?name value`
+    Keyword(String, Range),
+}
+
+impl Argument {
+    pub fn range_mut(&mut self) -> &mut Range {
+        match self {
+            Argument::Positional(range)
+            | Argument::String(_, range)
+            | Argument::Typed(_, range)
+            | Argument::Keyword(_, range) => range,
+        }
+    }
+
+    pub fn is_positional(&self) -> bool {
+        !matches!(self, Argument::Keyword(..))
+    }
 }
 
 impl Token {
@@ -94,16 +147,18 @@ impl Token {
         }
     }
 
-    pub fn to_completion_item(&mut self, at: Option<Position>) -> Option<CompletionItem> {
-        if at.map_or(true, |pos| self.in_scope(pos)) {
+    pub fn to_completion_item(&self, at: Option<Position>) -> Option<CompletionItem> {
+        if at.is_none_or(|pos| self.in_scope(pos)) {
             Some(CompletionItem {
                 label: self.name.to_owned(),
                 kind: self.kind.to_completion_item_kind(),
                 detail: Some(self.scope.value().to_owned()),
-                documentation: self
-                    .documentation
-                    .to_owned()
-                    .map(|doc| Documentation::String(doc)),
+                documentation: self.documentation.as_deref().map(|doc| {
+                    Documentation::MarkupContent(MarkupContent {
+                        kind: MarkupKind::Markdown,
+                        value: Docstring::parse(doc).markdown(),
+                    })
+                }),
                 ..Default::default()
             })
         } else {
@@ -111,8 +166,9 @@ impl Token {
         }
     }
 
-    pub fn to_document_symbol(&mut self, at: Option<Position>) -> Option<DocumentSymbol> {
-        if at.map_or(true, |pos| self.in_scope(pos)) {
+    #[allow(dead_code)]
+    pub fn to_document_symbol(&self, at: Option<Position>) -> Option<DocumentSymbol> {
+        if at.is_none_or(|pos| self.in_scope(pos)) {
             Some(DocumentSymbol {
                 name: self.name.to_owned(),
                 detail: Some(self.scope.value().to_owned()),
@@ -121,19 +177,32 @@ impl Token {
                 selection_range: self.place,
                 children: Some(vec![]),
                 tags: None,
+                #[allow(deprecated)]
                 deprecated: None,
             })
         } else {
             None
         }
     }
+
+    pub fn to_symbol_information(&self, uri: Url) -> SymbolInformation {
+        SymbolInformation {
+            name: self.name.to_owned(),
+            kind: self.kind.to_document_symbol_kind(),
+            tags: None,
+            #[allow(deprecated)]
+            deprecated: None,
+            location: Location::new(uri, self.place),
+            container_name: None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use tower_lsp::lsp_types::{Position, Range};
 
-    use crate::token::{Token, TokenKind, TokenScope, TokenUse};
+    use crate::token::{Token, TokenKind, TokenScope};
     #[test]
     fn token_scope() {
         let tok = Token {
@@ -165,6 +234,8 @@ mod tests {
                     character: 10,
                 },
             },
+            arguments: vec![],
+            value_type: None,
         };
         let tok2 = Token {
             kind: TokenKind::VariableAssignment,
@@ -173,6 +244,8 @@ mod tests {
             info: None,
             documentation: Some("Some description".to_string()),
             encloses: None,
+            arguments: vec![],
+            value_type: None,
             place: Range {
                 start: Position {
                     line: 3,
@@ -0,0 +1,207 @@
+use serde::{Deserialize, Serialize};
+
+/// the suffixes a number can be scaled with, `1.5u` is `1.5e-6`
+const SCALE_FACTORS: [(char, i32); 18] = [
+    ('Y', 24),
+    ('Z', 21),
+    ('E', 18),
+    ('P', 15),
+    ('T', 12),
+    ('G', 9),
+    ('M', 6),
+    ('k', 3),
+    ('K', 3),
+    ('%', -2),
+    ('m', -3),
+    ('u', -6),
+    ('n', -9),
+    ('p', -12),
+    ('f', -15),
+    ('a', -18),
+    ('z', -21),
+    ('y', -24),
+];
+
+/// the digits of a number literal and the power of ten its suffix scales them by
+fn scaled(text: &str) -> Option<(&str, i32)> {
+    if !text.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    }
+    let last = text.chars().last()?;
+    Some(
+        match SCALE_FACTORS.iter().find(|(suffix, _)| *suffix == last) {
+            Some((_, exponent)) => (&text[..text.len() - last.len_utf8()], *exponent),
+            None => (text, 0),
+        },
+    )
+}
+
+/// the value of a number literal, scale factors included
+pub fn number_value(text: &str) -> Option<f64> {
+    let (digits, exponent) = scaled(text)?;
+    if digits.contains('e') {
+        digits
+            .parse::<f64>()
+            .ok()
+            .map(|value| value * 10f64.powi(exponent))
+    } else {
+        format!("{digits}e{exponent}").parse().ok()
+    }
+}
+
+/// what a SKILL value is known to be
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ValueType {
+    Fixnum,
+    Flonum,
+    /// a fixnum or a flonum
+    Number,
+    String,
+    Symbol,
+    List,
+    DbObject,
+    /// `t` or `nil`
+    Boolean,
+    Procedure,
+}
+
+impl ValueType {
+    /// the type a parameter named like in the Cadence reference takes,
+    /// `t_string` takes a string while `g_value` takes anything
+    pub fn of_parameter(param: &str) -> Option<ValueType> {
+        match param.split_once('_')?.0 {
+            "x" => Some(ValueType::Fixnum),
+            "f" => Some(ValueType::Flonum),
+            "n" => Some(ValueType::Number),
+            "t" => Some(ValueType::String),
+            "s" => Some(ValueType::Symbol),
+            "l" => Some(ValueType::List),
+            "d" => Some(ValueType::DbObject),
+            "b" => Some(ValueType::Boolean),
+            "u" => Some(ValueType::Procedure),
+            _ => None,
+        }
+    }
+
+    /// the type called `name` in a docstring, in any case or by a common alias
+    pub fn of_name(name: &str) -> Option<ValueType> {
+        match name.to_lowercase().as_str() {
+            "fixnum" | "integer" | "int" => Some(ValueType::Fixnum),
+            "flonum" | "float" => Some(ValueType::Flonum),
+            "number" => Some(ValueType::Number),
+            "string" => Some(ValueType::String),
+            "symbol" => Some(ValueType::Symbol),
+            "list" => Some(ValueType::List),
+            "dbobject" | "dbobj" => Some(ValueType::DbObject),
+            "t/nil" | "boolean" | "bool" => Some(ValueType::Boolean),
+            "procedure" | "function" => Some(ValueType::Procedure),
+            _ => None,
+        }
+    }
+
+    /// the type of a literal as written
+    pub fn of_literal(text: &str) -> Option<ValueType> {
+        match text {
+            "t" | "nil" => Some(ValueType::Boolean),
+            _ if text.starts_with('"') => Some(ValueType::String),
+            _ if text.starts_with("'(") => Some(ValueType::List),
+            _ if text.starts_with('\'') => Some(ValueType::Symbol),
+            _ if text.starts_with(|c: char| c.is_ascii_digit()) => {
+                // scaled numbers are always flonums
+                if text.contains(['.', 'e']) || scaled(text).is_some_and(|(_, exp)| exp != 0) {
+                    Some(ValueType::Flonum)
+                } else {
+                    Some(ValueType::Fixnum)
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// the result of arithmetic on operands of these types, `None` if any is unknown
+    pub fn of_arithmetic(operands: &[Option<ValueType>]) -> Option<ValueType> {
+        operands
+            .iter()
+            .try_fold(ValueType::Fixnum, |result, operand| {
+                match (result, (*operand)?) {
+                    (_, ValueType::Flonum) | (ValueType::Flonum, _) => Some(ValueType::Flonum),
+                    (ValueType::Fixnum, ValueType::Fixnum) => Some(ValueType::Fixnum),
+                    (_, ValueType::Fixnum | ValueType::Number) => Some(ValueType::Number),
+                    _ => None,
+                }
+            })
+    }
+
+    pub fn is_number(self) -> bool {
+        matches!(
+            self,
+            ValueType::Fixnum | ValueType::Flonum | ValueType::Number
+        )
+    }
+
+    /// whether passing a value of this type where `expected` is wanted is an obvious
+    /// mistake, only numbers and strings are told apart to stay clear of false alarms
+    pub fn mismatches(self, expected: ValueType) -> bool {
+        (self.is_number() && expected == ValueType::String)
+            || (self == ValueType::String && expected.is_number())
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ValueType::Fixnum => "fixnum",
+            ValueType::Flonum => "flonum",
+            ValueType::Number => "number",
+            ValueType::String => "string",
+            ValueType::Symbol => "symbol",
+            ValueType::List => "list",
+            ValueType::DbObject => "dbObject",
+            ValueType::Boolean => "t/nil",
+            ValueType::Procedure => "procedure",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{number_value, ValueType};
+
+    #[test]
+    fn value_types() {
+        assert_eq!(ValueType::of_parameter("t_string"), Some(ValueType::String));
+        assert_eq!(ValueType::of_parameter("g_value"), None);
+        assert_eq!(ValueType::of_parameter("name"), None);
+
+        assert_eq!(ValueType::of_name("dbObject"), Some(ValueType::DbObject));
+        assert_eq!(ValueType::of_name("t/nil"), Some(ValueType::Boolean));
+        assert_eq!(ValueType::of_name("thing"), None);
+
+        assert_eq!(ValueType::of_literal("12"), Some(ValueType::Fixnum));
+        assert_eq!(ValueType::of_literal("1.5e3"), Some(ValueType::Flonum));
+        assert_eq!(ValueType::of_literal("\"12\""), Some(ValueType::String));
+        assert_eq!(ValueType::of_literal("'(1 2)"), Some(ValueType::List));
+        assert_eq!(ValueType::of_literal("nil"), Some(ValueType::Boolean));
+        assert_eq!(ValueType::of_literal("10k"), Some(ValueType::Flonum));
+
+        assert_eq!(number_value("1.5u"), Some(1.5e-6));
+        assert_eq!(number_value("10k"), Some(10000.0));
+        assert_eq!(number_value("2e3m"), Some(2.0));
+        assert_eq!(number_value("42"), Some(42.0));
+        assert_eq!(number_value("k10"), None);
+
+        let fixnum = Some(ValueType::Fixnum);
+        assert_eq!(ValueType::of_arithmetic(&[fixnum, fixnum]), fixnum);
+        assert_eq!(
+            ValueType::of_arithmetic(&[fixnum, Some(ValueType::Flonum)]),
+            Some(ValueType::Flonum)
+        );
+        assert_eq!(
+            ValueType::of_arithmetic(&[Some(ValueType::Number), fixnum]),
+            Some(ValueType::Number)
+        );
+        assert_eq!(ValueType::of_arithmetic(&[fixnum, None]), None);
+
+        assert!(ValueType::Fixnum.mismatches(ValueType::String));
+        assert!(ValueType::String.mismatches(ValueType::Number));
+        assert!(!ValueType::Boolean.mismatches(ValueType::Number));
+    }
+}
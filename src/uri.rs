@@ -0,0 +1,63 @@
+use std::path::{Path, PathBuf};
+
+use tower_lsp::lsp_types::Url;
+
+/// the local path of a `file` URI, with percent escapes decoded
+pub fn uri_to_path(uri: &Url) -> Option<PathBuf> {
+    if uri.scheme() != "file" {
+        return None;
+    }
+    uri.to_file_path().ok()
+}
+
+/// the key a document is cached under, the path for files on disk and
+/// the whole URI for unsaved buffers like `untitled:Untitled-1`
+pub fn uri_to_key(uri: &Url) -> String {
+    uri_to_path(uri)
+        .and_then(|path| path.to_str().map(str::to_owned))
+        .unwrap_or_else(|| uri.to_string())
+}
+
+/// the URI a cache key was made from
+pub fn key_to_uri(key: &str) -> Option<Url> {
+    if Path::new(key).is_absolute() {
+        Url::from_file_path(key).ok()
+    } else {
+        Url::parse(key).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use tower_lsp::lsp_types::Url;
+
+    use super::{key_to_uri, uri_to_key, uri_to_path};
+
+    #[test]
+    fn tricky_paths() {
+        for path in [
+            "/work/my pdk/skill/util.il",
+            "/work/pdk/Müller/ÜberCell.il",
+            "/work/pdk/100%/a#b?c.il",
+            "/work/pdk/[cells]/x.il",
+        ] {
+            let uri = key_to_uri(path).unwrap();
+            assert_eq!(uri.scheme(), "file");
+            assert_eq!(uri_to_path(&uri), Some(PathBuf::from(path)));
+            assert_eq!(uri_to_key(&uri), path);
+        }
+
+        let uri = Url::parse("file:///work/my%20pdk/M%C3%BCller.il").unwrap();
+        assert_eq!(uri_to_key(&uri), "/work/my pdk/Müller.il");
+    }
+
+    #[test]
+    fn unsaved_buffers() {
+        let uri = Url::parse("untitled:Untitled-1").unwrap();
+        assert_eq!(uri_to_path(&uri), None);
+        assert_eq!(uri_to_key(&uri), "untitled:Untitled-1");
+        assert_eq!(key_to_uri(&uri_to_key(&uri)), Some(uri));
+    }
+}
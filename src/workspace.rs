@@ -0,0 +1,783 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{canonicalize, metadata};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use dashmap::DashMap;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::WalkBuilder;
+use log::info;
+use tower_lsp::lsp_types::{Diagnostic, Position, Range, Url};
+
+use crate::cache::TokenCache;
+use crate::config::Config;
+use crate::lint::declared_globals;
+use crate::rules::UNDEFINED_VARIABLE;
+use crate::token::{Token, TokenKind, TokenScope};
+
+/// files Virtuoso evaluates on its own, every load chain starts at one of these
+const ENTRY_POINTS: [&str; 3] = [".cdsinit", "libInit.il", "libInit.ils"];
+
+/// extensions of SKILL and SKILL++ sources
+const SKILL_EXTENSIONS: [&str; 2] = ["il", "ils"];
+
+fn file_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("")
+}
+
+pub fn is_entry_point(path: &str) -> bool {
+    ENTRY_POINTS.contains(&file_name(path))
+}
+
+fn has_skill_extension(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| SKILL_EXTENSIONS.contains(&ext))
+}
+
+pub fn is_skill_file(path: &str) -> bool {
+    has_skill_extension(path) || is_entry_point(path)
+}
+
+/// glob patterns matching every file `is_skill_file` accepts
+pub fn skill_file_globs() -> Vec<String> {
+    SKILL_EXTENSIONS
+        .iter()
+        .map(|ext| format!("**/*.{ext}"))
+        .chain(
+            ENTRY_POINTS
+                .iter()
+                .filter(|name| !has_skill_extension(name))
+                .map(|name| format!("**/{name}")),
+        )
+        .collect()
+}
+
+fn declares_global(tok: &Token) -> bool {
+    matches!(
+        tok.kind,
+        TokenKind::VariableAssignment | TokenKind::Function
+    ) && matches!(tok.scope, TokenScope::Global(_))
+}
+
+/// what other files see of a file, the load graph and the checks across files
+/// only have to be recomputed when this changes
+#[derive(Debug, PartialEq)]
+pub struct Exports {
+    /// global definitions and the end of every `load` call in file order
+    outline: Vec<(String, Option<Position>)>,
+    /// globals assigned outside of procedures
+    globals: Vec<String>,
+    /// heads and docstrings of procedures, their signatures come from these
+    procedures: Vec<(Option<String>, Option<String>)>,
+}
+
+impl Exports {
+    pub fn of(tokens: &[Token]) -> Exports {
+        Exports {
+            outline: tokens
+                .iter()
+                .filter_map(|tok| match tok.kind {
+                    TokenKind::Load => Some((tok.name.clone(), Some(tok.place.end))),
+                    _ if declares_global(tok) => Some((tok.name.clone(), None)),
+                    _ => None,
+                })
+                .collect(),
+            globals: declared_globals(tokens).map(str::to_owned).collect(),
+            procedures: tokens
+                .iter()
+                .filter(|tok| tok.kind == TokenKind::Function)
+                .map(|tok| (tok.info.clone(), tok.documentation.clone()))
+                .collect(),
+        }
+    }
+}
+
+/// `.ignore`, `.gitignore` and `.git/info/exclude` of a directory, in the order
+/// the walker consults them
+#[derive(Debug)]
+struct IgnoreFiles([Gitignore; 3]);
+
+impl IgnoreFiles {
+    fn read(dir: &Path) -> IgnoreFiles {
+        IgnoreFiles([".ignore", ".gitignore", ".git/info/exclude"].map(|name| {
+            let mut builder = GitignoreBuilder::new(dir);
+            let file = dir.join(name);
+            if file.is_file() {
+                if let Some(err) = builder.add(&file) {
+                    info!("invalid pattern in '{}': {}", file.display(), err);
+                }
+            }
+            builder.build().unwrap_or_else(|_| Gitignore::empty())
+        }))
+    }
+}
+
+/// the ignore files the walker respects, a directory is read when a file below it
+/// is checked for the first time and kept until the settings change
+#[derive(Debug)]
+struct Ignores {
+    dirs: DashMap<PathBuf, Arc<IgnoreFiles>>,
+    global: Gitignore,
+}
+
+impl Ignores {
+    fn new() -> Ignores {
+        // anchored like the walker does it, so the patterns apply to absolute paths
+        let (global, err) = GitignoreBuilder::new("").build_global();
+        if let Some(err) = err {
+            info!("invalid pattern in the global gitignore: {}", err);
+        }
+        Ignores {
+            dirs: DashMap::new(),
+            global,
+        }
+    }
+
+    fn files_in(&self, dir: &Path) -> Arc<IgnoreFiles> {
+        self.dirs
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| Arc::new(IgnoreFiles::read(dir)))
+            .clone()
+    }
+
+    /// whether walking the folder skips `path`, the deepest `.ignore` with a matching
+    /// pattern decides first, then `.gitignore`, `.git/info/exclude` and the global gitignore
+    fn is_ignored(&self, path: &Path) -> bool {
+        if path.components().any(|part| part.as_os_str() == ".git") {
+            return true;
+        }
+        let dirs: Vec<Arc<IgnoreFiles>> = path
+            .ancestors()
+            .skip(1)
+            .map(|dir| self.files_in(dir))
+            .collect();
+
+        (0..3)
+            .find_map(|at| {
+                dirs.iter()
+                    .map(|files| files.0[at].matched_path_or_any_parents(path, false))
+                    .find(|matched| !matched.is_none())
+            })
+            .unwrap_or_else(|| self.global.matched_path_or_any_parents(path, false))
+            .is_ignore()
+    }
+}
+
+/// the include and exclude globs of `config` anchored at `root`
+fn globs(config: &Config, root: &Path) -> Override {
+    let mut builder = OverrideBuilder::new(root);
+    for glob in config.include.iter() {
+        if let Err(err) = builder.add(glob) {
+            info!("invalid include glob {:?}: {}", glob, err);
+        }
+    }
+    for glob in config.exclude.iter() {
+        if let Err(err) = builder.add(&format!("!{glob}")) {
+            info!("invalid exclude glob {:?}: {}", glob, err);
+        }
+    }
+    builder.build().unwrap_or_else(|_| Override::empty())
+}
+
+/// a workspace folder together with the settings that apply to it
+#[derive(Debug, Clone)]
+pub struct Folder {
+    pub uri: Url,
+    pub root: PathBuf,
+    /// change it with `set_config`, the matchers below are built from it
+    pub config: Config,
+    /// the folder and its library roots, each with the globs anchored at it
+    roots: Vec<(PathBuf, Override)>,
+    ignores: Arc<Ignores>,
+}
+
+impl Folder {
+    pub fn new(uri: Url, root: PathBuf, config: Config) -> Folder {
+        let mut folder = Folder {
+            uri,
+            root,
+            config: Config::default(),
+            roots: vec![],
+            ignores: Arc::new(Ignores::new()),
+        };
+        folder.set_config(config);
+        folder
+    }
+
+    /// applies new settings, the ignore files are read again after this
+    pub fn set_config(&mut self, config: Config) {
+        self.roots = [&self.root]
+            .into_iter()
+            .chain(&config.library_roots)
+            .map(|root| (root.clone(), globs(&config, root)))
+            .collect();
+        self.ignores = Arc::new(Ignores::new());
+        self.config = config;
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        Path::new(path).starts_with(&self.root)
+    }
+
+    /// whether `path` is indexed because of this folder
+    pub fn indexes(&self, path: &str) -> bool {
+        let Some((root, globs)) = self.root_of(path) else {
+            return false;
+        };
+
+        let excluded = Path::new(path)
+            .ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(root) && *dir != root)
+            .any(|dir| globs.matched(dir, true).is_ignore());
+        let too_large = metadata(path).is_ok_and(|meta| meta.len() > self.config.max_file_size());
+
+        !excluded
+            && !too_large
+            && !globs.matched(path, false).is_ignore()
+            && !self.ignores.is_ignored(Path::new(path))
+    }
+
+    /// the folder or library root `path` is found in, with its globs
+    fn root_of(&self, path: &str) -> Option<&(PathBuf, Override)> {
+        self.roots
+            .iter()
+            .find(|(root, _)| Path::new(path).starts_with(root))
+    }
+
+    /// SKILL files below the folder and its library roots, skipping what `.gitignore`
+    /// and `.ignore` files, the exclude globs or the size limit rule out
+    pub fn skill_files(&self) -> Vec<String> {
+        let mut files = vec![];
+        let mut seen = HashSet::new();
+
+        for (root, globs) in self.roots.iter() {
+            let walk = WalkBuilder::new(root)
+                // `.cdsinit` is hidden but has to be indexed
+                .hidden(false)
+                .require_git(false)
+                .follow_links(true)
+                .max_filesize(Some(self.config.max_file_size()))
+                .overrides(globs.clone())
+                .filter_entry(|entry| entry.file_name() != ".git")
+                .build();
+
+            for entry in walk {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    // symlink cycles end up here as well
+                    Err(err) => {
+                        info!("skipped while indexing '{}': {}", root.display(), err);
+                        continue;
+                    }
+                };
+                let Some(path) = entry.path().to_str() else {
+                    continue;
+                };
+                if !is_skill_file(path) || entry.file_type().is_some_and(|ft| ft.is_dir()) {
+                    continue;
+                }
+                // the same file reached through several links is only indexed once
+                let real = canonicalize(path).unwrap_or_else(|_| entry.path().to_path_buf());
+                if seen.insert(real) {
+                    files.push(path.to_owned());
+                }
+            }
+        }
+
+        files
+    }
+}
+
+/// the innermost folder `path` belongs to, its settings apply to the file
+pub fn owning_folder<'a>(folders: &'a [Folder], path: &str) -> Option<&'a Folder> {
+    folders
+        .iter()
+        .filter(|folder| folder.contains(path))
+        .max_by_key(|folder| folder.root.components().count())
+}
+
+/// directories `load` calls in `path` are resolved against, files outside
+/// of any folder use the search path of every folder
+pub fn search_dirs(folders: &[Folder], path: &str) -> Vec<PathBuf> {
+    let owning = folders.iter().filter(|folder| folder.contains(path));
+    let relevant: Vec<&Folder> = if owning.clone().next().is_some() {
+        owning.collect()
+    } else {
+        folders.iter().collect()
+    };
+
+    relevant
+        .iter()
+        .flat_map(|folder| {
+            folder
+                .config
+                .skill_path
+                .iter()
+                .chain([&folder.root])
+                .cloned()
+        })
+        .collect()
+}
+
+/// global names defined along the load chains of the workspace
+#[derive(Debug, Default)]
+pub struct LoadGraph {
+    /// definition counter at which a global name got defined for the first time
+    first_defined: HashMap<String, usize>,
+
+    /// definition counter at the start of a file and after each of its `load` calls
+    checkpoints: HashMap<String, Vec<(Position, usize)>>,
+
+    /// every global name defined anywhere in the workspace
+    defined: HashSet<String>,
+}
+
+struct LoadWalker<'a> {
+    cache: &'a TokenCache,
+    search_dirs: &'a dyn Fn(&str) -> Vec<PathBuf>,
+    files: HashMap<PathBuf, String>,
+    visited: HashSet<String>,
+    counter: usize,
+    graph: LoadGraph,
+}
+
+impl LoadWalker<'_> {
+    /// finds the cached file a `load` call refers to, relative paths are tried
+    /// against the loading file first and the search directories after that
+    fn resolve(&self, from: &str, target: &str) -> Option<String> {
+        let target = Path::new(target);
+        let candidates = if target.is_absolute() {
+            vec![target.to_path_buf()]
+        } else {
+            Path::new(from)
+                .parent()
+                .into_iter()
+                .map(|dir| dir.to_path_buf())
+                .chain((self.search_dirs)(from))
+                .map(|dir| dir.join(target))
+                .collect()
+        };
+
+        candidates
+            .iter()
+            .filter_map(|candidate| canonicalize(candidate).ok())
+            .find_map(|candidate| self.files.get(&candidate).cloned())
+    }
+
+    fn visit(&mut self, path: &str) {
+        if !self.visited.insert(path.to_owned()) {
+            return;
+        }
+        let Some(tokens) = self.cache.symbols.get(path).map(|toks| toks.clone()) else {
+            return;
+        };
+
+        let mut checkpoints = vec![(Position::default(), self.counter)];
+        for tok in tokens {
+            if declares_global(&tok) && !self.graph.first_defined.contains_key(&tok.name) {
+                self.counter += 1;
+                self.graph.first_defined.insert(tok.name, self.counter);
+            } else if tok.kind == TokenKind::Load {
+                if let Some(target) = self.resolve(path, &tok.name) {
+                    self.visit(&target);
+                    checkpoints.push((tok.place.end, self.counter));
+                }
+            }
+        }
+        self.graph.checkpoints.insert(path.to_owned(), checkpoints);
+    }
+}
+
+impl LoadGraph {
+    /// follows the `load` calls of all entry points found in `cache`, `.cdsinit` first
+    pub fn resolve(cache: &TokenCache, search_dirs: &dyn Fn(&str) -> Vec<PathBuf>) -> LoadGraph {
+        let mut entries = vec![];
+        let mut files = HashMap::new();
+        let mut defined = HashSet::new();
+        for entry in cache.symbols.iter() {
+            if let Ok(canonical) = canonicalize(entry.key()) {
+                files.insert(canonical, entry.key().to_owned());
+            }
+            if is_entry_point(entry.key()) {
+                entries.push(entry.key().to_owned());
+            }
+            defined.extend(
+                entry
+                    .value()
+                    .iter()
+                    .filter(|tok| declares_global(tok))
+                    .map(|tok| tok.name.to_owned()),
+            );
+        }
+        entries.sort_by_key(|path| (file_name(path) != ".cdsinit", path.to_owned()));
+
+        let mut walker = LoadWalker {
+            cache,
+            search_dirs,
+            files,
+            visited: HashSet::new(),
+            counter: 0,
+            graph: LoadGraph {
+                defined,
+                ..Default::default()
+            },
+        };
+        for entry in entries {
+            walker.visit(&entry);
+        }
+
+        walker.graph
+    }
+
+    /// whether `name` is defined by the time `at` in `path` gets evaluated,
+    /// files outside of any load chain can see every global of the workspace
+    pub fn is_defined(&self, path: &str, name: &str, at: Position) -> bool {
+        match self.checkpoints.get(path) {
+            Some(checkpoints) => {
+                let reached = checkpoints
+                    .iter()
+                    .filter(|(pos, _)| *pos <= at)
+                    .map(|(_, counter)| *counter)
+                    .max()
+                    .unwrap_or(0);
+                self.first_defined
+                    .get(name)
+                    .is_some_and(|counter| *counter <= reached)
+            }
+            None => self.defined.contains(name),
+        }
+    }
+
+    /// reports every variable of `path` that is neither bound locally nor
+    /// defined as a global when it is evaluated
+    pub fn undefined_variables(&self, path: &str, tokens: &[Token]) -> Vec<Diagnostic> {
+        let mut locals: HashMap<&str, Vec<&Token>> = HashMap::new();
+        let mut first_assigned: HashMap<&str, Position> = HashMap::new();
+        let mut bodies: Vec<Range> = vec![];
+        for tok in tokens {
+            if declares_global(tok) {
+                let first = first_assigned.entry(&tok.name).or_insert(tok.place.start);
+                *first = (*first).min(tok.place.start);
+            } else if tok.kind == TokenKind::VariableAssignment {
+                locals.entry(&tok.name).or_default().push(tok);
+            }
+            if tok.kind == TokenKind::Function {
+                bodies.extend(tok.encloses);
+            }
+        }
+        // only the outermost procedures, these do not overlap and can be searched
+        bodies.sort_by_key(|body| body.start);
+        let mut outermost: Vec<Range> = vec![];
+        for body in bodies {
+            if outermost.last().is_none_or(|last| last.end <= body.start) {
+                outermost.push(body);
+            }
+        }
+
+        tokens
+            .iter()
+            .filter(|tok| tok.kind == TokenKind::VariableUse)
+            .filter(|tok| {
+                let at = tok.place.start;
+                let name = tok.name.as_str();
+                let bound_locally = locals
+                    .get(name)
+                    .is_some_and(|decls| decls.iter().any(|decl| decl.in_scope(at)));
+                let assigned_before = first_assigned.get(name).is_some_and(|first| *first <= at);
+                // procedure bodies run when called, by then every global may exist
+                let following = outermost.partition_point(|body| body.start < at);
+                let deferred = following > 0 && at < outermost[following - 1].end;
+
+                !(bound_locally
+                    || assigned_before
+                    || (deferred && self.defined.contains(name))
+                    || self.is_defined(path, name, at))
+            })
+            .map(|tok| {
+                UNDEFINED_VARIABLE
+                    .diagnostic(tok.place, "variable used before declaration".to_string())
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, write};
+    use std::path::{Path, PathBuf};
+
+    use tower_lsp::lsp_types::Url;
+
+    use crate::cache::TokenCache;
+    use crate::config::Config;
+
+    use crate::skill::parse_skill_chunks;
+
+    use super::{is_skill_file, search_dirs, skill_file_globs, Exports, Folder, LoadGraph};
+
+    fn folder(root: &Path, config: Config) -> Folder {
+        Folder::new(
+            Url::from_directory_path(root).unwrap(),
+            root.to_path_buf(),
+            config,
+        )
+    }
+
+    fn workspace(name: &str, files: &[(&str, &str)]) -> (PathBuf, TokenCache) {
+        let root = std::env::temp_dir().join(format!("srls-{}-{}", name, std::process::id()));
+        let cache = TokenCache::new();
+        for (file, content) in files {
+            let path = root.join(file);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(&path, content).unwrap();
+            cache.update(path.to_str().unwrap()).unwrap();
+        }
+        (root, cache)
+    }
+
+    fn undefined(graph: &LoadGraph, cache: &TokenCache, path: &Path) -> Vec<String> {
+        let path = path.to_str().unwrap();
+        let tokens = cache.symbols.get(path).unwrap();
+        graph
+            .undefined_variables(path, &tokens)
+            .into_iter()
+            .map(|diag| {
+                tokens
+                    .iter()
+                    .find(|tok| tok.place == diag.range)
+                    .unwrap()
+                    .name
+                    .clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn globals_follow_load_order() {
+        let (root, cache) = workspace(
+            "load-order",
+            &[
+                (".cdsinit", "first = 1\nload(\"skill/a.il\")\nlast = 2\n"),
+                ("skill/a.il", "second = first\nload(\"b.il\")\n"),
+                ("skill/b.il", "third = second\nfourth = last\n"),
+            ],
+        );
+        let graph = LoadGraph::resolve(&cache, &|_| vec![root.clone()]);
+
+        assert!(undefined(&graph, &cache, &root.join("skill/a.il")).is_empty());
+        assert_eq!(
+            undefined(&graph, &cache, &root.join("skill/b.il")),
+            vec!["last".to_string()]
+        );
+    }
+
+    #[test]
+    fn procedures_see_all_globals() {
+        let (root, cache) = workspace(
+            "procedures",
+            &[
+                ("libInit.il", "load(\"lib.il\")\nlate = 1\n"),
+                (
+                    "lib.il",
+                    "procedure(libFun(arg)\n  list(arg late)\n)\nearly = late\n",
+                ),
+                ("orphan.il", "x = late + missing\n"),
+            ],
+        );
+        let graph = LoadGraph::resolve(&cache, &|_| vec![root.clone()]);
+
+        assert_eq!(
+            undefined(&graph, &cache, &root.join("lib.il")),
+            vec!["late".to_string()]
+        );
+        assert_eq!(
+            undefined(&graph, &cache, &root.join("orphan.il")),
+            vec!["missing".to_string()]
+        );
+    }
+
+    #[test]
+    fn syntax_words_and_lambda_parameters() {
+        let (root, cache) = workspace(
+            "syntax-words",
+            &[(
+                "words.il",
+                "procedure(g(a) if(a then b = 1 else b = 2))\n\
+                 f = lambda((x) x)\n(lambda (y) list(y z))\n\
+                 foreach(mapcar e list(1) e)\ncase(b (one 1) ((two three) 2) (t unknown))\n\
+                 procedure(h() prog(() go(done) done return(1)))\n",
+            )],
+        );
+        let graph = LoadGraph::resolve(&cache, &|_| vec![root.clone()]);
+
+        assert_eq!(
+            undefined(&graph, &cache, &root.join("words.il")),
+            vec!["z".to_string(), "unknown".to_string()]
+        );
+    }
+
+    #[test]
+    fn exports() {
+        let exports = |text: &str| Exports::of(&parse_skill_chunks(text).unwrap().0);
+        let file = "a = 1\nload(\"b.il\")\nprocedure(f(x) plus(x a))\n";
+
+        // edits inside of procedures or of local variables stay within the file
+        assert_eq!(
+            exports(file),
+            exports("a = 1\nload(\"b.il\")\nprocedure(f(x) let((y) y = x) a)\n")
+        );
+        assert_ne!(
+            exports(file),
+            exports("a = 1\nload(\"c.il\")\nprocedure(f(x) x)\n")
+        );
+        assert_ne!(
+            exports(file),
+            exports("\na = 1\nload(\"b.il\")\nprocedure(f(x) x)\n")
+        );
+        assert_ne!(
+            exports(file),
+            exports("load(\"b.il\")\na = 1\nprocedure(f(x) x)\n")
+        );
+        assert_ne!(
+            exports(file),
+            exports("a = 1\nload(\"b.il\")\nprocedure(f(x y) x)\n")
+        );
+        assert_ne!(
+            exports(file),
+            exports("a = 1\nload(\"b.il\")\nprocedure(g(x) x)\n")
+        );
+    }
+
+    #[test]
+    fn search_dirs_of_owning_folder() {
+        let folder = |root: &str, skill_path: &[&str]| {
+            Folder::new(
+                Url::from_directory_path(root).unwrap(),
+                PathBuf::from(root),
+                Config {
+                    skill_path: skill_path.iter().map(PathBuf::from).collect(),
+                    ..Default::default()
+                },
+            )
+        };
+        let folders = [
+            folder("/work/pdk_a", &["/work/pdk_a/skill"]),
+            folder("/work/pdk_b", &[]),
+        ];
+
+        assert_eq!(
+            search_dirs(&folders, "/work/pdk_b/libInit.il"),
+            vec![PathBuf::from("/work/pdk_b")]
+        );
+        assert_eq!(
+            search_dirs(&folders, "/opt/cadence/local/util.il"),
+            vec![
+                PathBuf::from("/work/pdk_a/skill"),
+                PathBuf::from("/work/pdk_a"),
+                PathBuf::from("/work/pdk_b")
+            ]
+        );
+    }
+
+    #[test]
+    fn skill_files() {
+        assert!(is_skill_file("/work/pdk/skill/util.il"));
+        assert!(is_skill_file("/work/pdk/skill/class.ils"));
+        assert!(is_skill_file("/home/user/.cdsinit"));
+        assert!(!is_skill_file("/work/pdk/cds.lib"));
+        assert!(!is_skill_file("/work/pdk/skill/util.il.orig"));
+        assert_eq!(
+            skill_file_globs(),
+            vec!["**/*.il", "**/*.ils", "**/.cdsinit"]
+        );
+    }
+
+    #[test]
+    fn ignored_files() {
+        let (root, _) = workspace(
+            "ignored",
+            &[
+                (".gitignore", "build/\n*.bak.il\n"),
+                (".cdsinit", "load(\"skill/a.il\")\n"),
+                ("skill/a.il", "a = 1\n"),
+                ("skill/a.bak.il", "a = 0\n"),
+                ("skill/vendor/.ignore", "huge.il\n"),
+                ("skill/vendor/huge.il", "v = 1\n"),
+                ("skill/vendor/small.il", "v = 2\n"),
+                ("skill/generated/cells.il", "g = 1\n"),
+                ("build/out.il", "b = 1\n"),
+                (".git/hooks/x.il", "h = 1\n"),
+                (".git/info/exclude", "scratch/\n"),
+                ("skill/scratch/try.il", "t = 1\n"),
+                ("big.il", &"x = 1\n".repeat(100)),
+            ],
+        );
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("skill/loop")).unwrap();
+
+        let workspace = folder(
+            &root,
+            Config {
+                exclude: vec!["generated/".to_string()],
+                max_file_size: Some(100),
+                ..Default::default()
+            },
+        );
+        let mut files: Vec<String> = workspace
+            .skill_files()
+            .into_iter()
+            .map(|file| {
+                file.strip_prefix(root.to_str().unwrap())
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+        files.sort();
+
+        assert_eq!(
+            files,
+            vec!["/.cdsinit", "/skill/a.il", "/skill/vendor/small.il"]
+        );
+        assert!(workspace.indexes(root.join("skill/a.il").to_str().unwrap()));
+        assert!(workspace.indexes(root.join("skill/vendor/small.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("skill/generated/cells.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("skill/a.bak.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("skill/vendor/huge.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("build/out.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join(".git/hooks/x.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("skill/scratch/try.il").to_str().unwrap()));
+        assert!(!workspace.indexes(root.join("big.il").to_str().unwrap()));
+    }
+
+    #[test]
+    fn included_files() {
+        let (root, _) = workspace(
+            "included",
+            &[
+                ("skill/a.il", "a = 1\n"),
+                ("skill/b.ils", "b = 1\n"),
+                ("other/c.il", "c = 1\n"),
+            ],
+        );
+        let workspace = folder(
+            &root,
+            Config {
+                include: vec!["skill/*.il".to_string()],
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            workspace.skill_files(),
+            vec![root.join("skill/a.il").to_str().unwrap().to_owned()]
+        );
+        assert!(!workspace.indexes(root.join("other/c.il").to_str().unwrap()));
+    }
+}